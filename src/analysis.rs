@@ -0,0 +1,159 @@
+// Analysis Trait
+// Capture-wide measurements and transforms for any Waveform
+
+use crate::average::average_blocks;
+use crate::baseline::correct_frames;
+use crate::ensemble::ensemble;
+use crate::filter::filter_frames;
+use crate::integrate::integrate_frames;
+use crate::measure::measure_frames;
+use crate::peaks::find_peaks_frames;
+use crate::stats::capture_stats;
+use crate::timing::timing_frames;
+use crate::{Baseline, CaptureStats, EnsembleStatistic, Filter, FrameStats, IntegrationOptions, IntegrationReport};
+use crate::{MeasureOptions, PartialBlock, PeakOptions, PeakReport, PulseReport, Result, TimingOptions, TimingReport};
+use crate::{Waveform, WfmFile};
+#[cfg(feature = "fft")]
+use crate::analytic::analytic_frames;
+#[cfg(feature = "fft")]
+use crate::spectrum::spectrum_frames;
+#[cfg(feature = "fft")]
+use crate::{AnalyticQuantity, Spectrum, SpectrumOptions};
+
+/// Measurements and transforms of a whole capture
+///
+/// Implemented for every [`Waveform`], so the same calls work on a loaded
+/// [`WfmFile`] and on a [`WfmReader`](crate::WfmReader) streaming from disk.
+/// Each frame is read once. Transforms return a new [`WfmFile`] that keeps
+/// the source's header and frame timestamps when it has them.
+pub trait Analysis: Waveform {
+    /// Summary statistics of a frame
+    fn frame_stats(&self, index: u32) -> Result<FrameStats> {
+        Ok(FrameStats::from_samples(&self.try_frame(index)?))
+    }
+
+    /// Summary statistics of every frame and of the whole capture
+    fn stats(&self) -> Result<CaptureStats> {
+        capture_stats(self)
+    }
+
+    /// Pulse measurements of every frame, see [`measure_pulse`](crate::measure_pulse)
+    fn measure_pulses(&self, options: &MeasureOptions) -> Result<PulseReport> {
+        measure_frames(self, options)
+    }
+
+    /// Frequency, period, duty cycle and jitter of every frame, see
+    /// [`measure_timing`](crate::measure_timing)
+    fn measure_timing(&self, options: &TimingOptions) -> Result<TimingReport> {
+        timing_frames(self, options)
+    }
+
+    /// Gated area, charge and energy of every frame, see
+    /// [`integrate_pulse`](crate::integrate_pulse)
+    fn integrate(&self, options: &IntegrationOptions) -> Result<IntegrationReport> {
+        integrate_frames(self, options)
+    }
+
+    /// Peaks of every frame, see [`find_peaks`](crate::find_peaks)
+    fn find_peaks(&self, options: &PeakOptions) -> Result<PeakReport> {
+        find_peaks_frames(self, options)
+    }
+
+    /// Spectrum of every frame, or their average, see [`SpectrumOptions`]
+    #[cfg(feature = "fft")]
+    fn spectrum(&self, options: &SpectrumOptions) -> Result<Spectrum> {
+        spectrum_frames(self, options)
+    }
+
+    /// Per-sample statistic across all frames, as a new waveform with the
+    /// same time axis
+    fn ensemble(&self, statistic: EnsembleStatistic) -> Result<WfmFile> {
+        let frames: Vec<u32> = (0..self.num_frames()).collect();
+        ensemble(self, statistic, &frames)
+    }
+
+    /// Per-sample statistic across the given frames
+    fn ensemble_frames(&self, statistic: EnsembleStatistic, frames: &[u32]) -> Result<WfmFile> {
+        ensemble(self, statistic, frames)
+    }
+
+    /// Average every `n` consecutive frames into one, dropping a partial last block
+    fn average_blocks(&self, n: u32) -> Result<WfmFile> {
+        average_blocks(self, n, PartialBlock::Discard)
+    }
+
+    /// Average every `n` consecutive frames into one
+    ///
+    /// Each averaged frame takes the timestamp of the first frame in its
+    /// block.
+    fn average_blocks_with(&self, n: u32, partial: PartialBlock) -> Result<WfmFile> {
+        average_blocks(self, n, partial)
+    }
+
+    /// Apply `filter` to every frame
+    fn apply_filter(&self, filter: &Filter) -> Result<WfmFile> {
+        filter_frames(self, filter)
+    }
+
+    /// Remove the baseline of every frame
+    fn correct_baseline(&self, baseline: &Baseline) -> Result<WfmFile> {
+        correct_frames(self, baseline)
+    }
+
+    /// Envelope of every frame as a waveform on the same time axis
+    #[cfg(feature = "fft")]
+    fn envelope(&self) -> Result<WfmFile> {
+        analytic_frames(self, AnalyticQuantity::Envelope)
+    }
+
+    /// One analytic-signal quantity of every frame as a waveform
+    #[cfg(feature = "fft")]
+    fn analytic(&self, quantity: AnalyticQuantity) -> Result<WfmFile> {
+        analytic_frames(self, quantity)
+    }
+}
+
+impl<W: Waveform + ?Sized> Analysis for W {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_test_wfm;
+    use crate::{BaselineWindow, WfmReader};
+
+    #[test]
+    fn test_streamed_matches_loaded() {
+        let file = write_test_wfm(4, 100, |f, i| ((i as i32 + 10 * f as i32) % 50) as i8);
+        let loaded = WfmFile::open(file.path()).unwrap();
+        let reader = WfmReader::open(file.path()).unwrap();
+
+        assert_eq!(reader.stats().unwrap(), loaded.stats().unwrap());
+        assert_eq!(reader.frame_stats(2).unwrap(), loaded.frame_stats(2).unwrap());
+
+        let filter = Filter::moving_average(5).unwrap();
+        let streamed = reader.apply_filter(&filter).unwrap();
+        let filtered = loaded.apply_filter(&filter).unwrap();
+        assert_eq!(streamed.file_content.scaled_frames, filtered.file_content.scaled_frames);
+        assert_eq!(streamed.file_path, filtered.file_path);
+    }
+
+    #[test]
+    fn test_streamed_transform_keeps_timestamps() {
+        let file = write_test_wfm(4, 10, |f, _| f as i8);
+        let loaded = WfmFile::open(file.path()).unwrap();
+        let reader = WfmReader::open(file.path()).unwrap();
+        assert_eq!(reader.read_frame_timestamp(3).unwrap(), loaded.file_content.frame_timestamps[3]);
+
+        let averaged = reader.average_blocks(2).unwrap();
+        assert_eq!(averaged.num_frames(), 2);
+        assert_eq!(averaged.file_content.frame_timestamps[0].gmt_seconds, 1_700_000_000);
+        assert_eq!(averaged.file_content.frame_timestamps[1].gmt_seconds, 1_700_000_002);
+        assert_eq!(averaged.file_header.acq_time_scale, reader.file_header.acq_time_scale);
+
+        // Files built from samples keep their synthetic header
+        let samples = WfmFile::from_samples(10, 1e-9, 0.0, vec![1.0; 20]).unwrap();
+        let corrected = samples.correct_baseline(&Baseline::Mean(BaselineWindow::All)).unwrap();
+        assert_eq!(corrected.file_content.scaled_frames, vec![0.0; 20]);
+        assert_eq!(corrected.sample_interval(), 1e-9);
+    }
+}
@@ -0,0 +1,215 @@
+// Analytic Signal
+// Hilbert-transform envelope, instantaneous phase and frequency
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use rustfft::num_complex::Complex;
+use rustfft::{Fft, FftPlanner};
+
+use crate::{Result, Waveform, WfmFile};
+
+/// Quantity derived from the analytic signal of each frame
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnalyticQuantity {
+    /// Magnitude of the analytic signal, in vertical units
+    #[default]
+    Envelope,
+    /// Unwrapped instantaneous phase in radians
+    Phase,
+    /// Instantaneous frequency in hertz
+    Frequency,
+}
+
+impl AnalyticQuantity {
+    /// Parse a quantity name as used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "envelope" => Some(AnalyticQuantity::Envelope),
+            "phase" => Some(AnalyticQuantity::Phase),
+            "frequency" | "freq" => Some(AnalyticQuantity::Frequency),
+            _ => None,
+        }
+    }
+
+    /// Unit of the quantity, or `None` if it keeps the unit of the signal
+    pub fn unit(&self) -> Option<&'static str> {
+        match self {
+            AnalyticQuantity::Envelope => None,
+            AnalyticQuantity::Phase => Some("rad"),
+            AnalyticQuantity::Frequency => Some("Hz"),
+        }
+    }
+}
+
+/// Envelope, phase and frequency of one frame, one value per sample
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AnalyticSignal {
+    pub envelope: Vec<f64>,
+    /// Unwrapped phase in radians
+    pub phase: Vec<f64>,
+    /// Rate of change of the phase in hertz, from central differences
+    pub frequency: Vec<f64>,
+}
+
+impl AnalyticSignal {
+    /// Values of one quantity
+    pub fn quantity(&self, quantity: AnalyticQuantity) -> &[f64] {
+        match quantity {
+            AnalyticQuantity::Envelope => &self.envelope,
+            AnalyticQuantity::Phase => &self.phase,
+            AnalyticQuantity::Frequency => &self.frequency,
+        }
+    }
+}
+
+/// Analytic signal of one frame sampled every `sample_interval`
+///
+/// The Hilbert transform treats the frame as periodic, so the envelope and
+/// phase are least accurate within a few cycles of either end.
+pub fn analytic_signal(samples: &[f64], sample_interval: f64) -> AnalyticSignal {
+    Hilbert::new(samples.len()).analytic(samples, sample_interval)
+}
+
+/// Forward and inverse FFT plans reused across frames of equal length
+struct Hilbert {
+    forward: Arc<dyn Fft<f64>>,
+    inverse: Arc<dyn Fft<f64>>,
+    length: usize,
+}
+
+impl Hilbert {
+    fn new(length: usize) -> Self {
+        let mut planner = FftPlanner::new();
+        Hilbert {
+            forward: planner.plan_fft_forward(length),
+            inverse: planner.plan_fft_inverse(length),
+            length,
+        }
+    }
+
+    fn analytic(&self, samples: &[f64], sample_interval: f64) -> AnalyticSignal {
+        let n = self.length;
+        if n == 0 {
+            return AnalyticSignal::default();
+        }
+        let mut buffer: Vec<Complex<f64>> = samples.iter().map(|&v| Complex::new(v, 0.0)).collect();
+        self.forward.process(&mut buffer);
+
+        // Keep DC and Nyquist, double positive and drop negative frequencies
+        for (k, x) in buffer.iter_mut().enumerate() {
+            let gain = if k == 0 || 2 * k == n {
+                1.0
+            } else if 2 * k < n {
+                2.0
+            } else {
+                0.0
+            };
+            *x *= gain / n as f64;
+        }
+        self.inverse.process(&mut buffer);
+
+        let envelope = buffer.iter().map(|z| z.norm()).collect();
+        let mut phase: Vec<f64> = buffer.iter().map(|z| z.arg()).collect();
+        for i in 1..n {
+            let step = phase[i] - phase[i - 1];
+            phase[i] -= 2.0 * PI * (step / (2.0 * PI)).round();
+        }
+
+        let scale = 1.0 / (2.0 * PI * sample_interval);
+        let frequency = (0..n)
+            .map(|i| match (i.checked_sub(1), (i + 1 < n).then_some(i + 1)) {
+                (Some(a), Some(b)) => (phase[b] - phase[a]) / 2.0 * scale,
+                (None, Some(b)) => (phase[b] - phase[i]) * scale,
+                (Some(a), None) => (phase[i] - phase[a]) * scale,
+                (None, None) => 0.0,
+            })
+            .collect();
+
+        AnalyticSignal { envelope, phase, frequency }
+    }
+}
+
+/// One analytic-signal quantity of every frame of `source`
+///
+/// The header and frame timestamps are kept, so the result shares the
+/// original time axis; raw codes are dropped. Phase and frequency results
+/// report `rad` and `Hz` as their vertical unit.
+pub(crate) fn analytic_frames<S: Waveform + ?Sized>(source: &S, quantity: AnalyticQuantity) -> Result<WfmFile> {
+    let hilbert = Hilbert::new(source.samples_per_frame());
+    let mut scaled_frames = Vec::with_capacity(source.total_samples());
+    for i in 0..source.num_frames() {
+        let signal = hilbert.analytic(&source.try_frame(i)?, source.sample_interval());
+        scaled_frames.extend_from_slice(signal.quantity(quantity));
+    }
+    let mut result = WfmFile::derived(source, scaled_frames, 0..source.num_frames())?;
+    if let Some(unit) = quantity.unit() {
+        result.file_content.vertical_unit = Some(unit.to_string());
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Analysis, PeakOptions};
+
+    #[test]
+    fn test_analytic_signal() {
+        // 3 cycles per 64 samples at 1 kHz sampling: 46.875 Hz
+        let n = 64;
+        let samples: Vec<f64> = (0..n).map(|i| 2.0 * (2.0 * PI * 3.0 * i as f64 / n as f64 + 0.4).cos()).collect();
+        let signal = analytic_signal(&samples, 1e-3);
+
+        assert!(signal.envelope.iter().all(|e| (e - 2.0).abs() < 1e-9));
+        assert!((signal.phase[0] - 0.4).abs() < 1e-9);
+        assert!((signal.phase[n - 1] - signal.phase[0] - 2.0 * PI * 3.0 * 63.0 / 64.0).abs() < 1e-9);
+        assert!(signal.frequency.iter().all(|f| (f - 46.875).abs() < 1e-6));
+        assert_eq!(AnalyticQuantity::from_name("Phase"), Some(AnalyticQuantity::Phase));
+        assert!(analytic_signal(&[], 1.0).envelope.is_empty());
+    }
+
+    #[test]
+    fn test_envelope_waveform() {
+        // Gaussian-windowed 100 MHz bursts at 0.6 µs and 1.2 µs
+        let dt = 1e-9;
+        let burst = |t: f64, at: f64| (-((t - at) / 50e-9).powi(2)).exp();
+        let samples: Vec<f64> = (0..2 * 2000)
+            .map(|i| {
+                let t = (i % 2000) as f64 * dt;
+                (burst(t, 0.6e-6) + 0.5 * burst(t, 1.2e-6)) * (2.0 * PI * 100e6 * t).sin()
+            })
+            .collect();
+        let wfm = WfmFile::from_samples(2000, dt, 0.0, samples).unwrap();
+
+        let envelope = wfm.envelope().unwrap();
+        assert_eq!(envelope.num_frames(), 2);
+        assert_eq!(envelope.get_time_values(), wfm.get_time_values());
+        assert!((envelope.get_frame(1).unwrap()[600] - 1.0).abs() < 1e-3);
+
+        let peaks = envelope.find_peaks(&PeakOptions::new().prominence(0.1)).unwrap();
+        let times: Vec<f64> = peaks.frames[0].iter().map(|p| p.time).collect();
+        assert_eq!(times.len(), 2);
+        assert!((times[0] - 0.6e-6).abs() < 1e-10 && (times[1] - 1.2e-6).abs() < 1e-10);
+
+        assert_eq!(envelope.vertical_unit(), "V");
+
+        // Phase and frequency carry their own unit, also through later transforms
+        let frequency = wfm.analytic(AnalyticQuantity::Frequency).unwrap();
+        assert!((frequency.get_frame(0).unwrap()[600] - 100e6).abs() < 1e3);
+        assert_eq!(frequency.vertical_unit(), "Hz");
+        assert_eq!(wfm.analytic(AnalyticQuantity::Phase).unwrap().vertical_unit(), "rad");
+        let smoothed = frequency.apply_filter(&crate::Filter::moving_average(5).unwrap()).unwrap();
+        assert_eq!(smoothed.vertical_unit(), "Hz");
+
+        // Exports label the values with the unit and imports restore it
+        let out = tempfile::NamedTempFile::new().unwrap();
+        frequency.write_csv_with(out.path(), &crate::CsvOptions::new().metadata_header(true).units(true)).unwrap();
+        let text = std::fs::read_to_string(out.path()).unwrap();
+        assert!(text.contains("# vertical_unit: Hz") && text.contains("Voltage (Hz)"));
+        assert_eq!(WfmFile::from_csv(out.path()).unwrap().vertical_unit(), "Hz");
+        frequency.write_raw_binary(out.path(), crate::SampleType::F32).unwrap();
+        assert_eq!(WfmFile::read_raw_binary(out.path()).unwrap().vertical_unit(), "Hz");
+        std::fs::remove_file(crate::sidecar_path(out.path())).unwrap();
+    }
+}
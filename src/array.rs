@@ -0,0 +1,52 @@
+// ndarray Integration
+// Zero-copy frames x samples views of WfmFile data
+
+use ndarray::ArrayView2;
+
+use crate::WfmFile;
+
+impl WfmFile {
+    /// View the scaled data as a frames × samples matrix
+    ///
+    /// Returns `None` when the scaled values were not kept in memory
+    /// (see [`StoragePrecision::RawOnly`](crate::StoragePrecision::RawOnly)).
+    pub fn as_array2(&self) -> Option<ArrayView2<'_, f64>> {
+        ArrayView2::from_shape(self.array_shape(), &self.file_content.scaled_frames).ok()
+    }
+    
+    /// View the raw ADC codes as a frames × samples matrix
+    pub fn raw_array2(&self) -> Option<ArrayView2<'_, i8>> {
+        ArrayView2::from_shape(self.array_shape(), &self.file_content.raw_frames).ok()
+    }
+    
+    fn array_shape(&self) -> (usize, usize) {
+        (self.file_header.num_fastframes as usize, self.file_header.full_record_length as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Axis;
+    
+    #[test]
+    fn test_array_views() {
+        let mut wfm = WfmFile::new();
+        wfm.file_header.num_fastframes = 2;
+        wfm.file_header.full_record_length = 3;
+        wfm.file_content.raw_frames = vec![1, 2, 3, 4, 5, 6];
+        wfm.file_content.scaled_frames = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        
+        let data = wfm.as_array2().unwrap();
+        assert_eq!(data.dim(), (2, 3));
+        assert_eq!(data[[1, 0]], 4.0);
+        assert_eq!(data.as_ptr(), wfm.file_content.scaled_frames.as_ptr());
+        assert_eq!(data.mean_axis(Axis(0)).unwrap().to_vec(), vec![2.5, 3.5, 4.5]);
+        
+        let raw = wfm.raw_array2().unwrap();
+        assert_eq!(raw[[0, 2]], 3);
+        
+        wfm.file_content.scaled_frames.clear();
+        assert!(wfm.as_array2().is_none());
+    }
+}
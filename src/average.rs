@@ -0,0 +1,102 @@
+// Block Averaging
+// Average groups of consecutive FastFrames
+
+use crate::ensemble::{ensemble, EnsembleStatistic};
+use crate::{Result, Waveform, WfmError, WfmFile};
+
+/// What to do with trailing frames that do not fill a whole block
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartialBlock {
+    /// Drop the trailing frames
+    #[default]
+    Discard,
+    /// Average the trailing frames into one last, shorter block
+    Keep,
+    /// Fail unless the frame count is a multiple of the block size
+    Error,
+}
+
+impl PartialBlock {
+    /// Parse a partial block mode name as used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "discard" | "drop" => Some(PartialBlock::Discard),
+            "keep" => Some(PartialBlock::Keep),
+            "error" => Some(PartialBlock::Error),
+            _ => None,
+        }
+    }
+}
+
+/// Average every `n` consecutive frames of `source` into one
+///
+/// Each averaged frame takes the timestamp of the first frame in its block.
+/// The header is kept apart from the frame count; raw codes are dropped.
+pub(crate) fn average_blocks<S: Waveform + ?Sized>(source: &S, n: u32, partial: PartialBlock) -> Result<WfmFile> {
+    if n == 0 {
+        return Err(WfmError::ParseError("Block size must be at least 1".to_string()));
+    }
+    let frames = source.num_frames();
+    let remainder = frames % n;
+    if remainder != 0 && partial == PartialBlock::Error {
+        return Err(WfmError::ParseError(
+            format!("{} frames do not divide into blocks of {}", frames, n)
+        ));
+    }
+
+    let end = if partial == PartialBlock::Keep { frames } else { frames - remainder };
+    let starts: Vec<u32> = (0..end).step_by(n as usize).collect();
+    if starts.is_empty() {
+        return Err(WfmError::ParseError(
+            format!("{} frames are fewer than one block of {}", frames, n)
+        ));
+    }
+
+    let mut scaled_frames = Vec::with_capacity(starts.len() * source.samples_per_frame());
+    for &start in &starts {
+        let block: Vec<u32> = (start..(start + n).min(end)).collect();
+        let mean = ensemble(source, EnsembleStatistic::Mean, &block)?;
+        scaled_frames.extend(mean.file_content.scaled_frames);
+    }
+
+    WfmFile::derived(source, scaled_frames, starts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_test_wfm;
+    use crate::Analysis;
+
+    #[test]
+    fn test_average_blocks() {
+        let input = write_test_wfm(10, 20, |f, i| (f * 10 + i) as i8);
+        let wfm = WfmFile::open(input.path()).unwrap();
+
+        let averaged = wfm.average_blocks(4).unwrap();
+        assert_eq!(averaged.num_frames(), 2);
+        assert_eq!(averaged.get_time_values(), wfm.get_time_values());
+        assert!((averaged.get_frame(1).unwrap()[3] - 0.01 * (55.0 + 3.0)).abs() < 1e-12);
+        assert_eq!(averaged.file_content.frame_timestamps, [
+            wfm.file_content.frame_timestamps[0],
+            wfm.file_content.frame_timestamps[4],
+        ]);
+        assert!(averaged.file_content.raw_frames.is_empty());
+
+        let kept = wfm.average_blocks_with(4, PartialBlock::Keep).unwrap();
+        assert_eq!(kept.num_frames(), 3);
+        assert!((kept.get_frame(2).unwrap()[0] - 0.01 * 85.0).abs() < 1e-12);
+        assert_eq!(kept.file_content.frame_timestamps[2].gmt_seconds, 1_700_000_008);
+    }
+
+    #[test]
+    fn test_average_blocks_errors() {
+        let wfm = WfmFile::from_samples(2, 1.0, 0.0, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]).unwrap();
+        assert!(wfm.average_blocks(0).is_err());
+        assert!(wfm.average_blocks(4).is_err());
+        assert!(wfm.average_blocks_with(2, PartialBlock::Error).is_err());
+        assert_eq!(wfm.average_blocks_with(3, PartialBlock::Error).unwrap().get_frame(0).unwrap(), [3.0, 4.0]);
+        assert_eq!(wfm.average_blocks_with(4, PartialBlock::Keep).unwrap().num_frames(), 1);
+        assert_eq!(PartialBlock::from_name("Keep"), Some(PartialBlock::Keep));
+    }
+}
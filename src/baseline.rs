@@ -0,0 +1,189 @@
+// Baseline Correction
+// Per-frame offset and drift removal from reference windows
+
+use std::ops::Range;
+
+use crate::filter::solve;
+use crate::{Result, Waveform, WfmError, WfmFile};
+
+/// Samples of a frame used to estimate its baseline
+#[derive(Clone, Debug, PartialEq)]
+pub enum BaselineWindow {
+    /// Every sample before the trigger, where the time axis is negative
+    PreTrigger,
+    /// The whole frame
+    All,
+    /// Sample indices
+    Samples(Range<usize>),
+    /// Times in horizontal units measured from `acq_time_start`, so 0 is the
+    /// first sample
+    Time(Range<f64>),
+}
+
+impl BaselineWindow {
+    /// Sample indices covered by the window in a frame of `len` samples
+    fn indices(&self, len: usize, sample_interval: f64, t0: f64) -> Result<Range<usize>> {
+        // Index of the first sample at or after `offset` from the first sample
+        let index = |offset: f64| ((offset / sample_interval - 1e-9).ceil().max(0.0) as usize).min(len);
+        let range = match self {
+            BaselineWindow::PreTrigger => 0..index(-t0),
+            BaselineWindow::All => 0..len,
+            BaselineWindow::Samples(r) => r.start.min(len)..r.end.min(len),
+            BaselineWindow::Time(r) => index(r.start)..index(r.end),
+        };
+        if range.is_empty() {
+            return Err(WfmError::ParseError(format!("Baseline window {:?} holds no samples", self)));
+        }
+        Ok(range)
+    }
+}
+
+/// How the baseline of each frame is estimated and removed
+#[derive(Clone, Debug, PartialEq)]
+pub enum Baseline {
+    /// Subtract the mean of the window
+    Mean(BaselineWindow),
+    /// Subtract the median of the window
+    Median(BaselineWindow),
+    /// Subtract a least-squares polynomial of the given degree fitted to the window
+    Polynomial(usize, BaselineWindow),
+    /// Subtract the line through the means of two windows
+    Linear(BaselineWindow, BaselineWindow),
+}
+
+impl Baseline {
+    /// Baseline of one frame, evaluated at every sample
+    pub fn estimate(&self, samples: &[f64], sample_interval: f64, t0: f64) -> Result<Vec<f64>> {
+        let len = samples.len();
+        let window = |w: &BaselineWindow| -> Result<(Range<usize>, &[f64])> {
+            let range = w.indices(len, sample_interval, t0)?;
+            Ok((range.clone(), &samples[range]))
+        };
+
+        match self {
+            Baseline::Mean(w) => {
+                let (_, values) = window(w)?;
+                Ok(vec![mean(values); len])
+            }
+            Baseline::Median(w) => {
+                let (_, values) = window(w)?;
+                let mut sorted = values.to_vec();
+                sorted.sort_by(f64::total_cmp);
+                let mid = sorted.len() / 2;
+                let median = if sorted.len() % 2 == 1 { sorted[mid] } else { (sorted[mid - 1] + sorted[mid]) / 2.0 };
+                Ok(vec![median; len])
+            }
+            Baseline::Polynomial(degree, w) => {
+                let (range, values) = window(w)?;
+                if values.len() <= *degree {
+                    return Err(WfmError::ParseError(
+                        format!("A degree {} fit needs more than {} samples", degree, values.len())
+                    ));
+                }
+                // Fit against x in -1 ..= 1 over the frame to keep the normal equations well conditioned
+                let scale = (len.max(2) - 1) as f64 / 2.0;
+                let x = |i: usize| (i as f64 - scale) / scale;
+                let size = degree + 1;
+                let mut normal = vec![vec![0.0; size + 1]; size];
+                for (i, &v) in range.zip(values) {
+                    let xi = x(i);
+                    for (j, row) in normal.iter_mut().enumerate() {
+                        for (k, a) in row.iter_mut().take(size).enumerate() {
+                            *a += xi.powi((j + k) as i32);
+                        }
+                        row[size] += v * xi.powi(j as i32);
+                    }
+                }
+                let c = solve(normal);
+                Ok((0..len)
+                    .map(|i| c.iter().rev().fold(0.0, |acc, cj| acc * x(i) + cj))
+                    .collect())
+            }
+            Baseline::Linear(first, second) => {
+                let (r1, v1) = window(first)?;
+                let (r2, v2) = window(second)?;
+                let c1 = (r1.start + r1.end - 1) as f64 / 2.0;
+                let c2 = (r2.start + r2.end - 1) as f64 / 2.0;
+                if c1 == c2 {
+                    return Err(WfmError::ParseError("Linear baseline windows share the same centre".to_string()));
+                }
+                let (m1, m2) = (mean(v1), mean(v2));
+                let slope = (m2 - m1) / (c2 - c1);
+                Ok((0..len).map(|i| m1 + slope * (i as f64 - c1)).collect())
+            }
+        }
+    }
+
+    /// Subtract the baseline from one frame
+    pub fn correct(&self, samples: &[f64], sample_interval: f64, t0: f64) -> Result<Vec<f64>> {
+        let baseline = self.estimate(samples, sample_interval, t0)?;
+        Ok(samples.iter().zip(baseline).map(|(v, b)| v - b).collect())
+    }
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+/// Remove the baseline of every frame of `source`
+///
+/// The header and frame timestamps are kept; raw codes are dropped.
+pub(crate) fn correct_frames<S: Waveform + ?Sized>(source: &S, baseline: &Baseline) -> Result<WfmFile> {
+    let mut scaled_frames = Vec::with_capacity(source.total_samples());
+    for i in 0..source.num_frames() {
+        scaled_frames.extend(baseline.correct(&source.try_frame(i)?, source.sample_interval(), source.t0())?);
+    }
+    WfmFile::derived(source, scaled_frames, 0..source.num_frames())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_test_wfm;
+    use crate::Analysis;
+
+    #[test]
+    fn test_offset_baselines() {
+        // Pre-trigger samples 0..4 at t = -4 .. -1, a pulse afterwards
+        let samples = [1.0, 3.0, 2.0, 10.0, 5.0, 50.0, 5.0, 5.0];
+        let pre = Baseline::Mean(BaselineWindow::PreTrigger).correct(&samples, 1.0, -4.0).unwrap();
+        assert_eq!(pre[..4], [-3.0, -1.0, -2.0, 6.0]);
+
+        let median = Baseline::Median(BaselineWindow::Samples(0..3)).correct(&samples, 1.0, -4.0).unwrap();
+        assert_eq!(median[5], 48.0);
+
+        // 1.5 .. 4 s from the first sample covers samples 2 and 3
+        let time = Baseline::Mean(BaselineWindow::Time(1.5..4.0)).estimate(&samples, 1.0, -4.0).unwrap();
+        assert_eq!(time[0], 6.0);
+
+        assert!(Baseline::Mean(BaselineWindow::PreTrigger).correct(&samples, 1.0, 0.0).is_err());
+        assert!(Baseline::Mean(BaselineWindow::Samples(20..30)).correct(&samples, 1.0, 0.0).is_err());
+
+        let input = write_test_wfm(3, 300, |f, i| if i < 100 { f as i8 * 5 } else { 40 });
+        let wfm = WfmFile::open(input.path()).unwrap();
+        let corrected = wfm.correct_baseline(&Baseline::Mean(BaselineWindow::PreTrigger)).unwrap();
+        assert_eq!(corrected.num_frames(), 3);
+        assert_eq!(corrected.file_content.frame_timestamps, wfm.file_content.frame_timestamps);
+        assert!((corrected.get_frame(2).unwrap()[150] - 0.3).abs() < 1e-12);
+        assert!(corrected.get_frame(2).unwrap()[..100].iter().all(|v| v.abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_drift_baselines() {
+        // Quadratic drift with a pulse in the middle
+        let drift = |i: usize| 0.5 + 0.01 * i as f64 - 2e-4 * (i * i) as f64;
+        let samples: Vec<f64> = (0..100).map(|i| drift(i) + if (40..60).contains(&i) { 1.0 } else { 0.0 }).collect();
+
+        let poly = Baseline::Polynomial(2, BaselineWindow::Samples(0..40));
+        let corrected = poly.correct(&samples, 1.0, 0.0).unwrap();
+        assert!(corrected.iter().enumerate().all(|(i, v)| {
+            let expected = if (40..60).contains(&i) { 1.0 } else { 0.0 };
+            (v - expected).abs() < 1e-9
+        }));
+        assert!(Baseline::Polynomial(3, BaselineWindow::Samples(0..3)).correct(&samples, 1.0, 0.0).is_err());
+
+        let line: Vec<f64> = (0..100).map(|i| 2.0 + 0.1 * i as f64).collect();
+        let linear = Baseline::Linear(BaselineWindow::Samples(0..10), BaselineWindow::Samples(90..100));
+        assert!(linear.correct(&line, 1.0, 0.0).unwrap().iter().all(|v| v.abs() < 1e-12));
+    }
+}
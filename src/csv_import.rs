@@ -0,0 +1,380 @@
+// CSV Import
+// Load CSV written by the CSV exporter or saved by a Tektronix scope
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::{Result, WfmError, WfmFile};
+
+/// Rows and metadata read from a CSV file before the layout is interpreted
+#[derive(Default)]
+struct CsvTable {
+    /// `# key: value` comments and scope preamble entries
+    metadata: HashMap<String, String>,
+    /// Normalised column names: lower case without a ` (unit)` suffix
+    names: Vec<String>,
+    /// Values of every data row; empty fields are NaN
+    rows: Vec<Vec<f64>>,
+}
+
+fn parse_number(field: &str, decimal_comma: bool) -> Option<f64> {
+    if decimal_comma {
+        field.replace(',', ".").parse().ok()
+    } else {
+        field.parse().ok()
+    }
+}
+
+/// Column name without quotes, unit suffix and case
+fn normalise_name(name: &str) -> String {
+    let name = name.split(" (").next().unwrap_or(name);
+    name.trim().to_ascii_lowercase()
+}
+
+/// Whether any field names a column the exporter writes, in any order
+fn is_header_row(fields: &[&str]) -> bool {
+    fields.iter().any(|f| {
+        let name = normalise_name(f);
+        matches!(name.as_str(), "time" | "sample" | "voltage" | "raw") || name.starts_with("frame")
+    })
+}
+
+impl CsvTable {
+    fn parse(text: &str) -> Result<Self> {
+        let mut table = CsvTable::default();
+
+        let first = text.lines()
+            .find(|l| !l.trim().is_empty() && !l.starts_with('#'))
+            .unwrap_or_default();
+        let delimiter = [';', '\t', ',']
+            .into_iter()
+            .find(|&d| first.contains(d))
+            .unwrap_or(',');
+        let decimal_comma = delimiter != ',';
+
+        // Tek "save as CSV" with the preamble in the first two columns and
+        // time/value pairs in columns four and five
+        let mut tek_columns = false;
+
+        for line in text.lines() {
+            if let Some(comment) = line.strip_prefix('#') {
+                if let Some((key, value)) = comment.split_once(':') {
+                    table.metadata.insert(key.trim().to_string(), value.trim().to_string());
+                }
+                continue;
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split(delimiter)
+                .map(|f| f.trim().trim_matches('"'))
+                .collect();
+            let number = |f: &str| parse_number(f, decimal_comma);
+
+            if table.names.is_empty() && fields.len() >= 5 && fields[2].is_empty() &&
+               number(fields[0]).is_none() && number(fields[3]).is_some() && number(fields[4]).is_some() {
+                tek_columns = true;
+                if !fields[0].is_empty() {
+                    table.metadata.insert(fields[0].to_string(), fields[1].to_string());
+                }
+                table.rows.push(vec![number(fields[3]).unwrap(), number(fields[4]).unwrap()]);
+                continue;
+            }
+
+            if table.names.is_empty() && !tek_columns {
+                if is_header_row(&fields) {
+                    table.names = fields.iter().map(|f| normalise_name(f)).collect();
+                } else if number(fields[0]).is_none() {
+                    // Scope preamble line such as `Sample Interval,4e-10`
+                    table.metadata.insert(fields[0].to_string(), fields.get(1).unwrap_or(&"").to_string());
+                } else {
+                    return Err(WfmError::ParseError("CSV data starts before a header row".to_string()));
+                }
+                continue;
+            }
+
+            let row = fields.iter()
+                .map(|f| if f.is_empty() { Some(f64::NAN) } else { number(f) })
+                .collect::<Option<Vec<f64>>>()
+                .ok_or_else(|| WfmError::ParseError(format!("Invalid CSV row '{}'", line)))?;
+            table.rows.push(row);
+        }
+
+        if tek_columns {
+            table.names = vec!["time".to_string(), "voltage".to_string()];
+        }
+        if table.names.is_empty() || table.rows.is_empty() {
+            return Err(WfmError::ParseError("CSV file holds no waveform data".to_string()));
+        }
+        Ok(table)
+    }
+
+    fn column(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|n| n == name)
+    }
+
+    fn metadata_number(&self, keys: &[&str]) -> Option<f64> {
+        keys.iter().find_map(|k| self.metadata.get(*k)?.parse().ok())
+    }
+
+    fn value(&self, row: usize, column: usize) -> f64 {
+        self.rows[row].get(column).copied().unwrap_or(f64::NAN)
+    }
+
+    /// Split the rows of a long table into frames
+    ///
+    /// Uses the frame column when present; otherwise a new frame starts
+    /// wherever the sample index or time goes backwards. Without either,
+    /// frames are `full_record_length` rows long when the metadata gives it.
+    fn long_frames(&self) -> Vec<Vec<usize>> {
+        let frame = self.column("frame");
+        let restart = self.column("sample").or(self.column("time"));
+        let record_length = self.metadata_number(&["full_record_length"])
+            .filter(|&n| n >= 1.0)
+            .map(|n| n as usize);
+
+        let mut frames: Vec<Vec<usize>> = Vec::new();
+        for row in 0..self.rows.len() {
+            let new_frame = row == 0 || match (frame, restart) {
+                (Some(c), _) => self.value(row, c) != self.value(row - 1, c),
+                (None, Some(c)) => self.value(row, c) <= self.value(row - 1, c),
+                (None, None) => record_length.is_some_and(|n| row % n == 0),
+            };
+            if new_frame {
+                frames.push(Vec::new());
+            }
+            frames.last_mut().unwrap().push(row);
+        }
+        frames
+    }
+}
+
+impl WfmFile {
+    /// Load waveform data from a CSV file
+    ///
+    /// Accepts the long layout of [`write_csv`](Self::write_csv), the wide
+    /// layout of [`write_csv_by_frame`](Self::write_csv_by_frame) in any
+    /// column, delimiter and decimal-comma combination, and CSV saved by
+    /// Tektronix scopes. In scope CSV with a `TIME` column every further
+    /// column becomes a frame. A long table with only value columns is one
+    /// frame unless the metadata header gives the record length.
+    ///
+    /// The sample interval and start time come from a time column, then from
+    /// the metadata comments or scope preamble. Files with neither, such as
+    /// the default output of `write_csv`, lose their time axis: the sample
+    /// interval becomes 1 s and the start time 0 s, so times read as sample
+    /// indices. Write a time column or the metadata header to keep it.
+    /// Raw ADC codes are kept when the file has a raw column for every frame.
+    pub fn from_csv<P: AsRef<Path>>(input_file: P) -> Result<Self> {
+        let table = &CsvTable::parse(&fs::read_to_string(&input_file)?)?;
+        let time = table.column("time");
+
+        // Row indices of each frame, with the columns holding its values and raw codes
+        let mut frames: Vec<(Vec<usize>, usize, Option<usize>)> = Vec::new();
+        let raw = table.column("raw");
+        if let Some(values) = table.column("voltage").or(raw) {
+            frames.extend(table.long_frames().into_iter().map(|rows| (rows, values, raw)));
+        } else {
+            let all_rows: Vec<usize> = (0..table.rows.len()).collect();
+            for (c, name) in table.names.iter().enumerate() {
+                if name == "time" || name == "sample" || name.ends_with("_raw") {
+                    continue;
+                }
+                let raw = table.column(&format!("{}_raw", name));
+                frames.push((all_rows.clone(), c, raw));
+            }
+            // Raw codes alone, as written with `--columns sample,raw`
+            if frames.is_empty() {
+                for (c, name) in table.names.iter().enumerate() {
+                    if name.ends_with("_raw") {
+                        frames.push((all_rows.clone(), c, Some(c)));
+                    }
+                }
+            }
+        }
+
+        let record_length = frames.first().map_or(0, |(rows, _, _)| rows.len());
+        if frames.is_empty() || record_length == 0 {
+            return Err(WfmError::ParseError("CSV file holds no waveform columns".to_string()));
+        }
+        if let Some((rows, _, _)) = frames.iter().find(|(rows, _, _)| rows.len() != record_length) {
+            return Err(WfmError::ParseError(
+                format!("CSV frames differ in length: {} and {} samples", record_length, rows.len())
+            ));
+        }
+
+        let first_rows = &frames[0].0;
+        let (sample_interval, t0) = match time {
+            Some(c) => {
+                let t_first = table.value(first_rows[0], c);
+                let t_last = table.value(first_rows[record_length - 1], c);
+                let dt = if record_length > 1 {
+                    (t_last - t_first) / (record_length - 1) as f64
+                } else {
+                    table.metadata_number(&["acq_time_scale", "Sample Interval"]).unwrap_or(1.0)
+                };
+                (dt, t_first)
+            }
+            None => {
+                let dt = table.metadata_number(&["acq_time_scale", "Sample Interval"]).unwrap_or(1.0);
+                let t0 = table.metadata_number(&["acq_time_start"])
+                    .or_else(|| table.metadata_number(&["Trigger Point"]).map(|p| -p * dt))
+                    .unwrap_or(0.0);
+                (dt, t0)
+            }
+        };
+
+        let raw_frames: Option<Vec<i8>> = frames.iter()
+            .flat_map(|(rows, _, raw)| rows.iter().map(move |&r| raw.map(|c| table.value(r, c))))
+            .map(|v| v.filter(|v| v.is_finite()).map(|v| v as i8))
+            .collect();
+
+        let scale = table.metadata_number(&["voltage_scale"]).unwrap_or(1.0);
+        let offset = table.metadata_number(&["voltage_offset"]).unwrap_or(0.0);
+        let scaled_frames: Vec<f64> = frames.iter()
+            .flat_map(|(rows, c, raw)| rows.iter().map(move |&r| {
+                if raw == &Some(*c) {
+                    table.value(r, *c) * scale + offset
+                } else {
+                    table.value(r, *c)
+                }
+            }))
+            .collect();
+
+        let mut wfm = WfmFile::from_samples(record_length, sample_interval, t0, scaled_frames)?;
+        wfm.file_path = input_file.as_ref().to_string_lossy().to_string();
+        if let Some(raw_frames) = raw_frames {
+            wfm.file_content.raw_frames = raw_frames;
+            wfm.file_header.voltage_scale = scale;
+            wfm.file_header.voltage_offset = offset;
+        }
+        wfm.file_content.vertical_unit = table.metadata.get("vertical_unit").filter(|&unit| unit != "V").cloned();
+        Ok(wfm)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CsvColumn, CsvOptions, FloatFormat, Waveform};
+    use crate::test_support::write_test_wfm;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_round_trip_layouts() {
+        let input = write_test_wfm(3, 8, |f, i| (f as i32 * 5 - i as i32) as i8);
+        let wfm = WfmFile::open(input.path()).unwrap();
+        let out = NamedTempFile::new().unwrap();
+
+        let long = CsvOptions::new()
+            .columns(&[CsvColumn::Frame, CsvColumn::Time, CsvColumn::Voltage, CsvColumn::Raw])
+            .units(true)
+            .metadata_header(true);
+        let wide = CsvOptions::wide()
+            .columns(&[CsvColumn::Time, CsvColumn::Voltage])
+            .delimiter(';')
+            .decimal_comma(true)
+            .float_format(FloatFormat::Scientific(12));
+
+        for options in [long, wide] {
+            wfm.write_csv_with(out.path(), &options).unwrap();
+            let restored = WfmFile::from_csv(out.path()).unwrap();
+            assert_eq!(restored.num_frames(), 3);
+            assert_eq!(restored.samples_per_frame(), 8);
+            assert!((restored.sample_interval() - 1e-9).abs() < 1e-18);
+            assert!((restored.t0() + 100e-9).abs() < 1e-18);
+            for (a, b) in restored.file_content.scaled_frames.iter().zip(&wfm.file_content.scaled_frames) {
+                assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+            }
+        }
+
+        // Only the long layout above carried raw codes
+        wfm.write_csv_with(out.path(), &CsvOptions::new().columns(&[CsvColumn::Frame, CsvColumn::Raw])
+                                                        .metadata_header(true)).unwrap();
+        let restored = WfmFile::from_csv(out.path()).unwrap();
+        assert_eq!(restored.file_content.raw_frames, wfm.file_content.raw_frames);
+        assert_eq!(restored.file_content.scaled_frames, wfm.file_content.scaled_frames);
+
+        assert!((restored.sample_interval() - 1e-9).abs() < 1e-18);
+    }
+
+    #[test]
+    fn test_round_trip_column_order() {
+        let input = write_test_wfm(3, 8, |f, i| (f as i32 * 5 - i as i32) as i8);
+        let wfm = WfmFile::open(input.path()).unwrap();
+        let out = NamedTempFile::new().unwrap();
+
+        // Voltage first, with the frames split where the time restarts
+        let options = CsvOptions::new().columns(&[CsvColumn::Voltage, CsvColumn::Time, CsvColumn::Raw]);
+        wfm.write_csv_with(out.path(), &options).unwrap();
+        let restored = WfmFile::from_csv(out.path()).unwrap();
+        assert_eq!(restored.num_frames(), 3);
+        assert_eq!(restored.file_content.raw_frames, wfm.file_content.raw_frames);
+        assert!((restored.t0() + 100e-9).abs() < 1e-18);
+        for (a, b) in restored.file_content.scaled_frames.iter().zip(&wfm.file_content.scaled_frames) {
+            assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+        }
+
+        // Voltage only: one frame, or the original frames with the metadata header
+        let options = CsvOptions::new().columns(&[CsvColumn::Voltage]);
+        wfm.write_csv_with(out.path(), &options).unwrap();
+        let restored = WfmFile::from_csv(out.path()).unwrap();
+        assert_eq!((restored.num_frames(), restored.samples_per_frame()), (1, 24));
+        assert_eq!(restored.file_content.scaled_frames, wfm.file_content.scaled_frames);
+
+        wfm.write_csv_with(out.path(), &options.metadata_header(true)).unwrap();
+        let restored = WfmFile::from_csv(out.path()).unwrap();
+        assert_eq!((restored.num_frames(), restored.samples_per_frame()), (3, 8));
+        assert_eq!(restored.file_content.scaled_frames, wfm.file_content.scaled_frames);
+        assert!((restored.sample_interval() - 1e-9).abs() < 1e-18);
+    }
+
+    #[test]
+    fn test_default_csv_loses_time_axis() {
+        let input = write_test_wfm(3, 8, |f, i| (f as i32 * 5 - i as i32) as i8);
+        let wfm = WfmFile::open(input.path()).unwrap();
+        let out = NamedTempFile::new().unwrap();
+
+        // Frame, Sample and Voltage only: the samples survive, the time axis does not
+        wfm.write_csv(out.path()).unwrap();
+        let restored = WfmFile::from_csv(out.path()).unwrap();
+        assert_eq!(restored.num_frames(), 3);
+        assert_eq!(restored.file_content.scaled_frames, wfm.file_content.scaled_frames);
+        assert_eq!(restored.sample_interval(), 1.0);
+        assert_eq!(restored.t0(), 0.0);
+        assert_eq!(restored.time_at(5), 5.0);
+
+        wfm.write_csv_by_frame(out.path()).unwrap();
+        assert_eq!(WfmFile::from_csv(out.path()).unwrap().sample_interval(), 1.0);
+
+        // The metadata header carries it without a time column
+        wfm.write_csv_with(out.path(), &CsvOptions::new().metadata_header(true)).unwrap();
+        let restored = WfmFile::from_csv(out.path()).unwrap();
+        assert!((restored.sample_interval() - 1e-9).abs() < 1e-18);
+        assert!((restored.t0() + 100e-9).abs() < 1e-18);
+    }
+
+    #[test]
+    fn test_tek_scope_csv() {
+        let out = NamedTempFile::new().unwrap();
+
+        fs::write(out.path(), "Model,MSO64\nRecord Length,4\nSample Interval,2e-09\n\
+                               Trigger Point,1\n\nTIME,CH1,CH2\n-2e-09,0.1,1\n0,0.2,2\n\
+                               2e-09,0.3,3\n4e-09,0.4,4\n").unwrap();
+        let wfm = WfmFile::from_csv(out.path()).unwrap();
+        assert_eq!(wfm.num_frames(), 2);
+        assert_eq!(wfm.get_frame(1).unwrap(), &[1.0, 2.0, 3.0, 4.0]);
+        assert!((wfm.sample_interval() - 2e-9).abs() < 1e-18);
+        assert_eq!(wfm.t0(), -2e-9);
+
+        fs::write(out.path(), "Record Length,3,,-1.0e-06,0.5\nSample Interval,1.0e-06,,0.0e+00,0.25\n\
+                               Source,CH1,,1.0e-06,-0.5\n").unwrap();
+        let wfm = WfmFile::from_csv(out.path()).unwrap();
+        assert_eq!(wfm.num_frames(), 1);
+        assert_eq!(wfm.get_frame(0).unwrap(), &[0.5, 0.25, -0.5]);
+        assert!((wfm.sample_interval() - 1e-6).abs() < 1e-15);
+        assert_eq!(wfm.t0(), -1e-6);
+    }
+}
@@ -0,0 +1,168 @@
+// Ensemble Statistics
+// Per-sample statistics across the frames of a capture
+
+use crate::stats::Accumulator;
+use crate::{Result, Waveform, WfmError, WfmFile};
+
+/// Statistic computed at every sample index across frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EnsembleStatistic {
+    Mean,
+    /// Population standard deviation
+    StdDev,
+    Min,
+    Max,
+    /// Two frames: the minimum and the maximum
+    Envelope,
+    Median,
+}
+
+impl EnsembleStatistic {
+    /// Parse a statistic name as used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "mean" | "average" => Some(EnsembleStatistic::Mean),
+            "std" | "stddev" | "std_dev" => Some(EnsembleStatistic::StdDev),
+            "min" => Some(EnsembleStatistic::Min),
+            "max" => Some(EnsembleStatistic::Max),
+            "envelope" => Some(EnsembleStatistic::Envelope),
+            "median" => Some(EnsembleStatistic::Median),
+            _ => None,
+        }
+    }
+}
+
+/// Compute `statistic` across `frames` of `source`, reading each frame once
+///
+/// Non-finite samples are skipped. The result keeps the source's path,
+/// header and vertical unit, and every output frame takes the timestamp of
+/// the first frame in `frames`.
+pub(crate) fn ensemble<S: Waveform + ?Sized>(source: &S, statistic: EnsembleStatistic, frames: &[u32]) -> Result<WfmFile> {
+    if frames.is_empty() {
+        return Err(WfmError::ParseError("No frames selected for the ensemble".to_string()));
+    }
+    let samples = source.samples_per_frame();
+
+    let values = if statistic == EnsembleStatistic::Median {
+        // Gather every sample index into one contiguous column
+        let mut columns = vec![0.0; samples * frames.len()];
+        for (k, &i) in frames.iter().enumerate() {
+            for (j, &v) in source.try_frame(i)?.iter().enumerate() {
+                columns[j * frames.len() + k] = v;
+            }
+        }
+        columns.chunks_mut(frames.len()).map(median).collect()
+    } else {
+        let mut acc = vec![Accumulator::default(); samples];
+        for &i in frames {
+            for (a, &v) in acc.iter_mut().zip(source.try_frame(i)?.iter()) {
+                a.push(0, v);
+            }
+        }
+        let stats = acc.iter().map(Accumulator::finish);
+        match statistic {
+            EnsembleStatistic::Mean => stats.map(|s| s.mean).collect(),
+            EnsembleStatistic::StdDev => stats.map(|s| s.std_dev).collect(),
+            EnsembleStatistic::Min => stats.map(|s| s.min).collect(),
+            EnsembleStatistic::Max => stats.map(|s| s.max).collect(),
+            _ => {
+                let (min, max): (Vec<f64>, Vec<f64>) = stats.map(|s| (s.min, s.max)).unzip();
+                [min, max].concat()
+            }
+        }
+    };
+
+    let outputs = values.len() / samples.max(1);
+    WfmFile::derived(source, values, std::iter::repeat_n(frames[0], outputs))
+}
+
+/// Median of the finite values, or NaN if there are none
+fn median(values: &mut [f64]) -> f64 {
+    let mut n = 0;
+    for i in 0..values.len() {
+        if values[i].is_finite() {
+            values.swap(n, i);
+            n += 1;
+        }
+    }
+    let values = &mut values[..n];
+    if n == 0 {
+        return f64::NAN;
+    }
+
+    let (lower, &mut mid, _) = values.select_nth_unstable_by(n / 2, f64::total_cmp);
+    if n % 2 == 1 {
+        mid
+    } else {
+        let below = lower.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        (below + mid) / 2.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_test_wfm;
+    use crate::{Analysis, WfmReader};
+
+    #[test]
+    fn test_ensemble() {
+        let input = write_test_wfm(4, 30, |f, i| (f as i8 - 1) * (i % 5) as i8);
+        let wfm = WfmFile::open(input.path()).unwrap();
+
+        let mean = wfm.ensemble(EnsembleStatistic::Mean).unwrap();
+        assert_eq!(mean.num_frames(), 1);
+        assert_eq!(mean.get_time_values(), wfm.get_time_values());
+        // Factors -1, 0, 1, 2 average to 0.5
+        assert!((mean.get_frame(0).unwrap()[3] - 0.5 * 0.03).abs() < 1e-12);
+
+        let std = wfm.ensemble(EnsembleStatistic::StdDev).unwrap();
+        assert!((std.get_frame(0).unwrap()[1] - 0.01 * 1.25f64.sqrt()).abs() < 1e-12);
+
+        let envelope = wfm.ensemble(EnsembleStatistic::Envelope).unwrap();
+        assert_eq!(envelope.num_frames(), 2);
+        assert!((envelope.get_frame(0).unwrap()[4] + 0.04).abs() < 1e-12);
+        assert!((envelope.get_frame(1).unwrap()[4] - 0.08).abs() < 1e-12);
+
+        let reader = WfmReader::open(input.path()).unwrap();
+        let max = reader.ensemble(EnsembleStatistic::Max).unwrap();
+        assert_eq!(max.get_frame(0), envelope.get_frame(1));
+
+        let mut csv = Vec::new();
+        mean.write_csv_to(&mut csv, &crate::CsvOptions::new()).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 31);
+
+        // The source's path, header, timestamps and unit carry over
+        assert_eq!(envelope.file_path, wfm.file_path);
+        assert_eq!(envelope.file_header.voltage_scale, wfm.file_header.voltage_scale);
+        assert_eq!(envelope.file_header.num_fastframes, 2);
+        assert_eq!(envelope.file_content.frame_timestamps, vec![wfm.file_content.frame_timestamps[0]; 2]);
+        let subset = wfm.ensemble_frames(EnsembleStatistic::Mean, &[2, 3]).unwrap();
+        assert_eq!(subset.file_content.frame_timestamps, [wfm.file_content.frame_timestamps[2]]);
+
+        let mut scaled = WfmFile::from_samples(30, 1e-9, 0.0, vec![1.0; 60]).unwrap();
+        scaled.file_content.vertical_unit = Some("Hz".to_string());
+        assert_eq!(scaled.ensemble(EnsembleStatistic::Median).unwrap().vertical_unit(), "Hz");
+    }
+
+    #[test]
+    fn test_ensemble_median() {
+        let samples = vec![
+            1.0, 5.0,
+            3.0, f64::NAN,
+            2.0, 7.0,
+            9.0, 6.0,
+        ];
+        let wfm = WfmFile::from_samples(2, 1.0, 0.0, samples).unwrap();
+
+        let median = wfm.ensemble(EnsembleStatistic::Median).unwrap();
+        assert_eq!(median.get_frame(0).unwrap(), [2.5, 6.0]);
+
+        let median = wfm.ensemble_frames(EnsembleStatistic::Median, &[0, 2, 3]).unwrap();
+        assert_eq!(median.get_frame(0).unwrap(), [2.0, 6.0]);
+
+        assert!(wfm.ensemble_frames(EnsembleStatistic::Mean, &[]).is_err());
+        assert!(wfm.ensemble_frames(EnsembleStatistic::Mean, &[4]).is_err());
+        assert_eq!(EnsembleStatistic::from_name("std"), Some(EnsembleStatistic::StdDev));
+    }
+}
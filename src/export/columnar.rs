@@ -0,0 +1,180 @@
+// Columnar Export
+// Apache Parquet and Arrow IPC writers (requires the `arrow` feature)
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, Float64Array, Int8Array, UInt32Array};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::ipc::writer::FileWriter;
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+
+use super::TableLayout;
+use crate::{Result, Waveform, WfmFile};
+
+/// Rows per record batch in the wide layout
+const WIDE_BATCH_ROWS: usize = 65536;
+
+impl WfmFile {
+    /// Write the data as an Apache Parquet file
+    ///
+    /// The long layout has `frame`, `sample`, `time`, `voltage` and `raw`
+    /// columns; the wide layout has `time` and one `frame_<n>` column per
+    /// frame. Header fields are stored as key/value metadata on the schema.
+    pub fn write_parquet<P: AsRef<Path>>(&self, output_file: P, layout: TableLayout) -> Result<()> {
+        let file = File::create(output_file)?;
+        let schema = self.table_schema(layout);
+        let mut writer = ArrowWriter::try_new(file, schema.clone(), None)?;
+
+        self.for_each_batch(&schema, layout, |batch| Ok(writer.write(&batch)?))?;
+
+        writer.close()?;
+        Ok(())
+    }
+
+    /// Write the data as an Arrow IPC (Feather v2) file
+    ///
+    /// Header fields are stored as key/value metadata on the schema.
+    pub fn write_arrow_ipc<P: AsRef<Path>>(&self, output_file: P, layout: TableLayout) -> Result<()> {
+        let file = File::create(output_file)?;
+        let schema = self.table_schema(layout);
+        let mut writer = FileWriter::try_new(file, &schema)?;
+
+        self.for_each_batch(&schema, layout, |batch| Ok(writer.write(&batch)?))?;
+
+        writer.finish()?;
+        Ok(())
+    }
+
+    fn table_schema(&self, layout: TableLayout) -> SchemaRef {
+        let fields = match layout {
+            TableLayout::Long => vec![
+                Field::new("frame", DataType::UInt32, false),
+                Field::new("sample", DataType::UInt32, false),
+                Field::new("time", DataType::Float64, false),
+                Field::new("voltage", DataType::Float64, false),
+                Field::new("raw", DataType::Int8, true),
+            ],
+            TableLayout::Wide => std::iter::once(Field::new("time", DataType::Float64, false))
+                .chain((0..self.num_frames())
+                    .map(|i| Field::new(format!("frame_{}", i), DataType::Float64, false)))
+                .collect(),
+        };
+
+        let metadata: HashMap<String, String> = self.file_header.fields()
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+
+        Arc::new(Schema::new_with_metadata(fields, metadata))
+    }
+
+    /// Build record batches for the layout and pass each one to `write`
+    ///
+    /// The long layout emits one batch per frame, the wide layout emits
+    /// batches of [`WIDE_BATCH_ROWS`] rows, so memory stays bounded.
+    fn for_each_batch<F>(&self, schema: &SchemaRef, layout: TableLayout, mut write: F) -> Result<()>
+    where
+        F: FnMut(RecordBatch) -> Result<()>,
+    {
+        let samples = self.samples_per_frame();
+        let times = self.time_values();
+
+        match layout {
+            TableLayout::Long => {
+                let has_raw = self.file_content.raw_frames.len() == self.total_samples();
+
+                for frame_index in 0..self.num_frames() {
+                    let frame = self.frame(frame_index).unwrap_or_default();
+                    let start = frame_index as usize * samples;
+                    let raw: ArrayRef = if has_raw {
+                        Arc::new(Int8Array::from(self.file_content.raw_frames[start..start + samples].to_vec()))
+                    } else {
+                        Arc::new(Int8Array::from(vec![None; samples]))
+                    };
+
+                    let columns: Vec<ArrayRef> = vec![
+                        Arc::new(UInt32Array::from(vec![frame_index; samples])),
+                        Arc::new(UInt32Array::from_iter_values(0..samples as u32)),
+                        Arc::new(Float64Array::from(times.clone())),
+                        Arc::new(Float64Array::from(frame.into_owned())),
+                        raw,
+                    ];
+                    write(RecordBatch::try_new(schema.clone(), columns)?)?;
+                }
+            }
+            TableLayout::Wide => {
+                let frames: Vec<_> = (0..self.num_frames())
+                    .map(|i| self.frame(i).unwrap_or_default())
+                    .collect();
+
+                for start in (0..samples).step_by(WIDE_BATCH_ROWS) {
+                    let end = (start + WIDE_BATCH_ROWS).min(samples);
+                    let columns: Vec<ArrayRef> = std::iter::once(&times[..])
+                        .chain(frames.iter().map(|f| f.as_ref()))
+                        .map(|values| Arc::new(Float64Array::from(values[start..end].to_vec())) as ArrayRef)
+                        .collect();
+                    write(RecordBatch::try_new(schema.clone(), columns)?)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow::ipc::reader::FileReader;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use tempfile::NamedTempFile;
+
+    fn test_wfm() -> WfmFile {
+        let mut wfm = WfmFile::new();
+        wfm.file_header.num_fastframes = 2;
+        wfm.file_header.full_record_length = 3;
+        wfm.file_header.voltage_scale = 0.5;
+        wfm.file_header.acq_time_scale = 1e-9;
+        wfm.file_content.raw_frames = vec![1, 2, 3, 4, 5, 6];
+        wfm.file_content.scaled_frames = vec![0.5, 1.0, 1.5, 2.0, 2.5, 3.0];
+        wfm
+    }
+
+    #[test]
+    fn test_parquet_long() {
+        let out = NamedTempFile::new().unwrap();
+        test_wfm().write_parquet(out.path(), TableLayout::Long).unwrap();
+
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(out.path()).unwrap()).unwrap();
+        assert_eq!(builder.schema().metadata()["voltage_scale"], "0.5");
+
+        let batches: Vec<RecordBatch> = builder.build().unwrap().map(|b| b.unwrap()).collect();
+        let rows: usize = batches.iter().map(|b| b.num_rows()).sum();
+        assert_eq!(rows, 6);
+
+        let voltage: Vec<f64> = batches.iter()
+            .flat_map(|b| b.column(3).as_any().downcast_ref::<Float64Array>().unwrap().values().to_vec())
+            .collect();
+        assert_eq!(voltage, vec![0.5, 1.0, 1.5, 2.0, 2.5, 3.0]);
+    }
+
+    #[test]
+    fn test_arrow_ipc_wide() {
+        let out = NamedTempFile::new().unwrap();
+        test_wfm().write_arrow_ipc(out.path(), TableLayout::Wide).unwrap();
+
+        let reader = FileReader::try_new(File::open(out.path()).unwrap(), None).unwrap();
+        let schema = reader.schema();
+        assert_eq!(schema.fields().len(), 3);
+        assert_eq!(schema.field(2).name(), "frame_1");
+        assert_eq!(schema.metadata()["num_fastframes"], "2");
+
+        let batch = reader.into_iter().next().unwrap().unwrap();
+        let frame1 = batch.column(2).as_any().downcast_ref::<Float64Array>().unwrap();
+        assert_eq!(frame1.values().to_vec(), vec![2.0, 2.5, 3.0]);
+    }
+}
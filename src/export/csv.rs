@@ -0,0 +1,391 @@
+// CSV Export
+// Configurable CSV writer driven by CsvOptions
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use super::TableLayout;
+use crate::{Result, Waveform, WfmError, WfmFile, WfmHeader};
+
+/// Column that can be selected for CSV output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CsvColumn {
+    /// Frame index (long layout only)
+    Frame,
+    /// Sample index within the frame
+    Sample,
+    /// Time of the sample
+    Time,
+    /// Scaled sample value
+    Voltage,
+    /// Raw ADC code
+    Raw,
+}
+
+impl CsvColumn {
+    /// Parse a column name as used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "frame" => Some(CsvColumn::Frame),
+            "sample" => Some(CsvColumn::Sample),
+            "time" => Some(CsvColumn::Time),
+            "voltage" => Some(CsvColumn::Voltage),
+            "raw" => Some(CsvColumn::Raw),
+            _ => None,
+        }
+    }
+}
+
+/// How floating point values are printed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FloatFormat {
+    /// Shortest representation that round-trips
+    #[default]
+    Shortest,
+    /// Fixed number of decimal places
+    Fixed(usize),
+    /// Scientific notation with the given number of decimal places
+    Scientific(usize),
+}
+
+impl FloatFormat {
+    fn format(&self, value: f64, decimal_comma: bool) -> String {
+        let text = match *self {
+            FloatFormat::Shortest => value.to_string(),
+            FloatFormat::Fixed(p) => format!("{:.*}", p, value),
+            FloatFormat::Scientific(p) => format!("{:.*e}", p, value),
+        };
+        if decimal_comma {
+            text.replace('.', ",")
+        } else {
+            text
+        }
+    }
+}
+
+/// Options for [`WfmFile::write_csv_with`]
+///
+/// The defaults reproduce [`WfmFile::write_csv`]: one row per sample with
+/// frame index, sample index and voltage, comma separated.
+#[derive(Clone, Debug)]
+pub struct CsvOptions {
+    /// Row per sample of every frame, or frames in columns
+    pub layout: TableLayout,
+    /// Columns to write, in order
+    ///
+    /// In the wide layout `Sample` and `Time` are leading columns while
+    /// `Voltage` and `Raw` produce one column per frame; `Frame` is ignored.
+    pub columns: Vec<CsvColumn>,
+    /// Field separator; a `,` is written as `;` when `decimal_comma` is set
+    pub delimiter: char,
+    /// Print floats with a decimal comma
+    pub decimal_comma: bool,
+    /// Formatting of floating point values
+    pub float_format: FloatFormat,
+    /// Add units to the column names, e.g. `Time (s)`
+    pub units: bool,
+    /// Start the file with `#` comment lines listing the header fields
+    pub metadata_header: bool,
+    /// Frames to write, or all frames when `None`
+    pub frames: Option<Range<u32>>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            layout: TableLayout::Long,
+            columns: vec![CsvColumn::Frame, CsvColumn::Sample, CsvColumn::Voltage],
+            delimiter: ',',
+            decimal_comma: false,
+            float_format: FloatFormat::Shortest,
+            units: false,
+            metadata_header: false,
+            frames: None,
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Row per sample with frame, sample and voltage columns
+    pub fn new() -> Self {
+        CsvOptions::default()
+    }
+
+    /// Frames in columns with a leading sample column
+    pub fn wide() -> Self {
+        CsvOptions {
+            layout: TableLayout::Wide,
+            columns: vec![CsvColumn::Sample, CsvColumn::Voltage],
+            ..CsvOptions::default()
+        }
+    }
+
+    /// Set the table layout
+    pub fn layout(mut self, layout: TableLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Select the columns to write
+    pub fn columns(mut self, columns: &[CsvColumn]) -> Self {
+        self.columns = columns.to_vec();
+        self
+    }
+
+    /// Set the field separator
+    pub fn delimiter(mut self, delimiter: char) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Print floats with a decimal comma, separating fields with `;` unless
+    /// another delimiter than `,` is set
+    pub fn decimal_comma(mut self, decimal_comma: bool) -> Self {
+        self.decimal_comma = decimal_comma;
+        self
+    }
+
+    /// Separator written between fields, never `,` alongside decimal commas
+    pub(crate) fn field_delimiter(&self) -> char {
+        if self.decimal_comma && self.delimiter == ',' { ';' } else { self.delimiter }
+    }
+
+    /// Set the float formatting
+    pub fn float_format(mut self, float_format: FloatFormat) -> Self {
+        self.float_format = float_format;
+        self
+    }
+
+    /// Add units to the column names
+    pub fn units(mut self, units: bool) -> Self {
+        self.units = units;
+        self
+    }
+
+    /// Write a `#` comment header with the file metadata
+    pub fn metadata_header(mut self, metadata_header: bool) -> Self {
+        self.metadata_header = metadata_header;
+        self
+    }
+
+    /// Only write the given range of frames
+    pub fn frames(mut self, range: Range<u32>) -> Self {
+        self.frames = Some(range);
+        self
+    }
+}
+
+impl WfmFile {
+    /// Write the scaled data to a CSV file, one row per sample
+    ///
+    /// Columns are `Frame,Sample,Voltage`. See [`write_csv_with`](Self::write_csv_with)
+    /// for other layouts and formatting.
+    pub fn write_csv<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        self.write_csv_with(output_file, &CsvOptions::new())
+    }
+
+    /// Write frames as separate columns
+    pub fn write_csv_by_frame<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        self.write_csv_with(output_file, &CsvOptions::wide())
+    }
+
+    /// Write a CSV file with the given options
+    pub fn write_csv_with<P: AsRef<Path>>(&self, output_file: P, options: &CsvOptions) -> Result<()> {
+        let file = File::create(output_file)?;
+        let mut writer = BufWriter::new(file);
+
+        self.write_csv_to(&mut writer, options)?;
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write CSV data with the given options to any writer
+    pub fn write_csv_to<W: Write>(&self, writer: &mut W, options: &CsvOptions) -> Result<()> {
+        write_csv_table(self, Some((&self.file_path, &self.file_header)), writer, options)
+    }
+}
+
+/// Write any [`Waveform`] as CSV
+///
+/// `metadata` supplies the source path and header for the optional comment
+/// header. The wide layout holds every selected frame in memory.
+pub(crate) fn write_csv_table<S, W>(
+    source: &S,
+    metadata: Option<(&str, &WfmHeader)>,
+    writer: &mut W,
+    options: &CsvOptions,
+) -> Result<()>
+where
+    S: Waveform + ?Sized,
+    W: Write,
+{
+    if options.columns.is_empty() {
+        return Err(WfmError::ParseError("No CSV columns selected".to_string()));
+    }
+    
+    let range = options.frames.clone().unwrap_or(0..source.num_frames());
+    if range.start > range.end || range.end > source.num_frames() {
+        return Err(WfmError::InvalidFrameRange(range.start, range.end, source.num_frames()));
+    }
+    
+    if options.metadata_header {
+        if let Some((path, header)) = metadata {
+            if !path.is_empty() {
+                writeln!(writer, "# source: {}", path)?;
+            }
+            for (name, value) in header.fields() {
+                writeln!(writer, "# {}: {}", name, value)?;
+            }
+        }
+        writeln!(writer, "# vertical_unit: {}", source.vertical_unit())?;
+        writeln!(writer, "# horizontal_unit: {}", source.horizontal_unit())?;
+    }
+    
+    let delim = options.field_delimiter().to_string();
+    let fmt = |v: f64| options.float_format.format(v, options.decimal_comma);
+    let wants_raw = options.columns.contains(&CsvColumn::Raw);
+    let raw_at = |raw: &Option<Cow<[i8]>>, sample: usize| raw.as_ref()
+        .map(|r| r[sample].to_string())
+        .unwrap_or_default();
+    
+    match options.layout {
+        TableLayout::Long => {
+            let names: Vec<String> = options.columns.iter()
+                .map(|&c| column_name(source, c, options.units))
+                .collect();
+            writeln!(writer, "{}", names.join(&delim))?;
+            
+            for frame_num in range {
+                let frame = source.try_frame(frame_num)?;
+                let raw = if wants_raw { source.raw_frame(frame_num) } else { None };
+                for (sample_num, &value) in frame.iter().enumerate() {
+                    let fields: Vec<String> = options.columns.iter()
+                        .map(|c| match c {
+                            CsvColumn::Frame => frame_num.to_string(),
+                            CsvColumn::Sample => sample_num.to_string(),
+                            CsvColumn::Time => fmt(source.time_at(sample_num)),
+                            CsvColumn::Voltage => fmt(value),
+                            CsvColumn::Raw => raw_at(&raw, sample_num),
+                        })
+                        .collect();
+                    writeln!(writer, "{}", fields.join(&delim))?;
+                }
+            }
+        }
+        
+        TableLayout::Wide => {
+            let frames = range.clone()
+                .map(|i| Ok((source.try_frame(i)?,
+                             if wants_raw { source.raw_frame(i) } else { None })))
+                .collect::<Result<Vec<_>>>()?;
+            
+            // Write header
+            let mut names = Vec::new();
+            for &column in &options.columns {
+                match column {
+                    CsvColumn::Frame => {}
+                    CsvColumn::Sample | CsvColumn::Time => names.push(column_name(source, column, options.units)),
+                    CsvColumn::Voltage | CsvColumn::Raw => {
+                        let suffix = if column == CsvColumn::Raw { "_raw" } else { "" };
+                        let unit = if options.units && column == CsvColumn::Voltage {
+                            format!(" ({})", source.vertical_unit())
+                        } else {
+                            String::new()
+                        };
+                        names.extend(range.clone().map(|i| format!("Frame{}{}{}", i, suffix, unit)));
+                    }
+                }
+            }
+            writeln!(writer, "{}", names.join(&delim))?;
+            
+            // Write data row by row
+            for sample in 0..source.samples_per_frame() {
+                let mut fields = Vec::with_capacity(names.len());
+                for &column in &options.columns {
+                    match column {
+                        CsvColumn::Frame => {}
+                        CsvColumn::Sample => fields.push(sample.to_string()),
+                        CsvColumn::Time => fields.push(fmt(source.time_at(sample))),
+                        CsvColumn::Voltage => fields.extend(frames.iter().map(|(f, _)| fmt(f[sample]))),
+                        CsvColumn::Raw => fields.extend(frames.iter().map(|(_, r)| raw_at(r, sample))),
+                    }
+                }
+                writeln!(writer, "{}", fields.join(&delim))?;
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+fn column_name<S: Waveform + ?Sized>(source: &S, column: CsvColumn, units: bool) -> String {
+    match (column, units) {
+        (CsvColumn::Frame, _) => "Frame".to_string(),
+        (CsvColumn::Sample, _) => "Sample".to_string(),
+        (CsvColumn::Time, false) => "Time".to_string(),
+        (CsvColumn::Time, true) => format!("Time ({})", source.horizontal_unit()),
+        (CsvColumn::Voltage, false) => "Voltage".to_string(),
+        (CsvColumn::Voltage, true) => format!("Voltage ({})", source.vertical_unit()),
+        (CsvColumn::Raw, _) => "Raw".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_wfm() -> WfmFile {
+        let mut wfm = WfmFile::new();
+        wfm.file_header.num_fastframes = 2;
+        wfm.file_header.full_record_length = 2;
+        wfm.file_header.acq_time_scale = 0.5;
+        wfm.file_content.raw_frames = vec![1, 2, 3, 4];
+        wfm.file_content.scaled_frames = vec![0.25, 0.5, 0.75, 1.0];
+        wfm
+    }
+
+    fn to_string(wfm: &WfmFile, options: &CsvOptions) -> String {
+        let mut out = Vec::new();
+        wfm.write_csv_to(&mut out, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_default_layouts() {
+        let wfm = test_wfm();
+        assert_eq!(to_string(&wfm, &CsvOptions::new()),
+                   "Frame,Sample,Voltage\n0,0,0.25\n0,1,0.5\n1,0,0.75\n1,1,1\n");
+        assert_eq!(to_string(&wfm, &CsvOptions::wide()),
+                   "Sample,Frame0,Frame1\n0,0.25,0.75\n1,0.5,1\n");
+    }
+
+    #[test]
+    fn test_custom_options() {
+        let wfm = test_wfm();
+        let options = CsvOptions::new()
+            .columns(&[CsvColumn::Time, CsvColumn::Voltage, CsvColumn::Raw])
+            .delimiter(';')
+            .decimal_comma(true)
+            .float_format(FloatFormat::Fixed(2))
+            .units(true)
+            .frames(1..2);
+        assert_eq!(to_string(&wfm, &options), "Time (s);Voltage (V);Raw\n0,00;0,75;3\n0,50;1,00;4\n");
+
+        // A comma delimiter would clash with the decimal commas
+        assert_eq!(to_string(&wfm, &CsvOptions::new().decimal_comma(true).frames(0..1)),
+                   "Frame;Sample;Voltage\n0;0;0,25\n0;1;0,5\n");
+        assert_eq!(to_string(&wfm, &CsvOptions::new().decimal_comma(true).delimiter('\t').frames(0..1)),
+                   "Frame\tSample\tVoltage\n0\t0\t0,25\n0\t1\t0,5\n");
+
+        let options = CsvOptions::wide()
+            .columns(&[CsvColumn::Time, CsvColumn::Raw])
+            .metadata_header(true);
+        let text = to_string(&wfm, &options);
+        assert!(text.starts_with("# wfm_version: \n# num_impl_dim: 0\n"));
+        assert!(text.ends_with("Time,Frame0_raw,Frame1_raw\n0,1,3\n0.5,2,4\n"));
+    }
+}
@@ -0,0 +1,223 @@
+// MATLAB Export
+// Level 5 MAT-file writer, no MATLAB installation required
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use crate::{HeaderValue, Result, Waveform, WfmError, WfmFile};
+
+// Data types
+const MI_INT8: u32 = 1;
+const MI_UINT16: u32 = 4;
+const MI_INT32: u32 = 5;
+const MI_UINT32: u32 = 6;
+const MI_DOUBLE: u32 = 9;
+const MI_MATRIX: u32 = 14;
+
+// Array classes
+const MX_STRUCT_CLASS: u32 = 2;
+const MX_CHAR_CLASS: u32 = 4;
+const MX_DOUBLE_CLASS: u32 = 6;
+
+/// Length of each entry in a struct's field name table
+const FIELD_NAME_LEN: usize = 32;
+
+fn padded(len: usize) -> usize {
+    len.div_ceil(8) * 8
+}
+
+/// Write a data element tag, its payload and padding to 8 bytes
+fn write_element<W: Write>(w: &mut W, data_type: u32, bytes: &[u8]) -> io::Result<()> {
+    w.write_all(&data_type.to_le_bytes())?;
+    w.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    w.write_all(bytes)?;
+    w.write_all(&[0u8; 8][..padded(bytes.len()) - bytes.len()])
+}
+
+/// Bytes taken by the flags, dimensions and name sub-elements of a matrix
+fn matrix_prefix_len(dims: &[u32], name: &str) -> usize {
+    16 + 8 + padded(4 * dims.len()) + 8 + padded(name.len())
+}
+
+/// Write a miMATRIX tag followed by its flags, dimensions and name
+///
+/// `payload_len` is the size of the sub-elements the caller writes afterwards.
+fn write_matrix_prefix<W: Write>(w: &mut W, class: u32, dims: &[u32], name: &str, payload_len: usize) -> Result<()> {
+    let total = matrix_prefix_len(dims, name) + payload_len;
+    if total > u32::MAX as usize {
+        return Err(WfmError::ParseError(format!(
+            "MAT variable '{}' exceeds the 4 GiB limit of Level 5 MAT-files", name
+        )));
+    }
+
+    w.write_all(&MI_MATRIX.to_le_bytes())?;
+    w.write_all(&(total as u32).to_le_bytes())?;
+
+    let flags: Vec<u8> = [class, 0].iter().flat_map(|v| v.to_le_bytes()).collect();
+    write_element(w, MI_UINT32, &flags)?;
+    let dims: Vec<u8> = dims.iter().flat_map(|d| d.to_le_bytes()).collect();
+    write_element(w, MI_INT32, &dims)?;
+    write_element(w, MI_INT8, name.as_bytes())?;
+    Ok(())
+}
+
+fn write_double_matrix<W, I>(w: &mut W, name: &str, dims: &[u32], values: I) -> Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = f64>,
+{
+    let n: usize = dims.iter().map(|&d| d as usize).product();
+    write_matrix_prefix(w, MX_DOUBLE_CLASS, dims, name, 8 + padded(8 * n))?;
+
+    w.write_all(&MI_DOUBLE.to_le_bytes())?;
+    w.write_all(&((8 * n) as u32).to_le_bytes())?;
+    let mut written = 0;
+    for v in values.into_iter().take(n) {
+        w.write_all(&v.to_le_bytes())?;
+        written += 1;
+    }
+    // Pad short frames so the element size stays consistent
+    for _ in written..n {
+        w.write_all(&f64::NAN.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+fn char_matrix(name: &str, text: &str) -> Result<Vec<u8>> {
+    let units: Vec<u8> = text.encode_utf16().flat_map(|u| u.to_le_bytes()).collect();
+    let dims = [1, (units.len() / 2) as u32];
+
+    let mut buf = Vec::new();
+    write_matrix_prefix(&mut buf, MX_CHAR_CLASS, &dims, name, 8 + padded(units.len()))?;
+    write_element(&mut buf, MI_UINT16, &units)?;
+    Ok(buf)
+}
+
+impl WfmFile {
+    /// Write a Level 5 MAT-file
+    ///
+    /// The file holds three variables:
+    ///
+    /// - `frames`: samples × frames `double` matrix, so `frames(:, k)` is frame `k`
+    /// - `t`: samples × 1 time axis
+    /// - `header`: struct with every [`WfmHeader`](crate::WfmHeader) field
+    pub fn write_mat<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        let file = File::create(output_file)?;
+        let mut writer = BufWriter::new(file);
+
+        // 116 bytes of descriptive text, 8 bytes of subsystem offset, version and endian indicator
+        let mut text = format!("MATLAB 5.0 MAT-file, Platform: {}, Created by: wfm_reader {}",
+                               std::env::consts::OS, env!("CARGO_PKG_VERSION")).into_bytes();
+        text.resize(116, b' ');
+        writer.write_all(&text)?;
+        writer.write_all(&[0u8; 8])?;
+        writer.write_all(&0x0100u16.to_le_bytes())?;
+        writer.write_all(b"IM")?;
+
+        let samples = self.samples_per_frame() as u32;
+        let values = (0..self.num_frames())
+            .flat_map(|i| self.frame(i).unwrap_or_default().into_owned());
+        write_double_matrix(&mut writer, "frames", &[samples, self.num_frames()], values)?;
+
+        write_double_matrix(&mut writer, "t", &[samples, 1], self.time_values())?;
+
+        self.write_header_struct(&mut writer)?;
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn write_header_struct<W: Write>(&self, w: &mut W) -> Result<()> {
+        let fields = self.file_header.fields();
+
+        let mut names = vec![0u8; FIELD_NAME_LEN * fields.len()];
+        let mut values = Vec::new();
+        for (i, (name, value)) in fields.iter().enumerate() {
+            names[i * FIELD_NAME_LEN..i * FIELD_NAME_LEN + name.len()].copy_from_slice(name.as_bytes());
+
+            // Struct members are unnamed matrices
+            match value {
+                HeaderValue::Text(text) => values.extend(char_matrix("", text)?),
+                _ => {
+                    let v = value.as_f64().unwrap_or(f64::NAN);
+                    write_double_matrix(&mut values, "", &[1, 1], [v])?;
+                }
+            }
+        }
+
+        let payload_len = 8 + 8 + padded(names.len()) + values.len();
+        write_matrix_prefix(w, MX_STRUCT_CLASS, &[1, 1], "header", payload_len)?;
+
+        // Field name length uses the small data element format
+        w.write_all(&((4u32 << 16) | MI_INT32).to_le_bytes())?;
+        w.write_all(&(FIELD_NAME_LEN as u32).to_le_bytes())?;
+        write_element(w, MI_INT8, &names)?;
+        w.write_all(&values)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    /// Top-level variables as (class, dims, name, element bytes)
+    fn read_variables(bytes: &[u8]) -> Vec<(u32, Vec<u32>, String, Vec<u8>)> {
+        let u32_at = |b: &[u8], i: usize| u32::from_le_bytes(b[i..i + 4].try_into().unwrap());
+        let mut vars = Vec::new();
+        let mut pos = 128;
+
+        while pos < bytes.len() {
+            assert_eq!(u32_at(bytes, pos), MI_MATRIX);
+            let len = u32_at(bytes, pos + 4) as usize;
+            let element = &bytes[pos + 8..pos + 8 + len];
+
+            let class = u32_at(element, 8) & 0xff;
+            let dims_len = u32_at(element, 20) as usize;
+            let dims = (0..dims_len / 4).map(|i| u32_at(element, 24 + 4 * i)).collect();
+            let name_at = 24 + padded(dims_len);
+            let name_len = u32_at(element, name_at + 4) as usize;
+            let name = String::from_utf8(element[name_at + 8..name_at + 8 + name_len].to_vec()).unwrap();
+
+            vars.push((class, dims, name, element.to_vec()));
+            pos += 8 + len;
+        }
+        vars
+    }
+
+    #[test]
+    fn test_mat_layout() {
+        let mut wfm = WfmFile::new();
+        wfm.file_header.wfm_version = ":WFM#003".to_string();
+        wfm.file_header.num_fastframes = 2;
+        wfm.file_header.full_record_length = 3;
+        wfm.file_header.acq_time_scale = 0.5;
+        wfm.file_content.scaled_frames = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+
+        let out = NamedTempFile::new().unwrap();
+        wfm.write_mat(out.path()).unwrap();
+        let bytes = std::fs::read(out.path()).unwrap();
+
+        assert!(bytes.starts_with(b"MATLAB 5.0 MAT-file"));
+        assert_eq!(&bytes[124..128], &[0x00, 0x01, b'I', b'M']);
+
+        let vars = read_variables(&bytes);
+        assert_eq!(vars.len(), 3);
+
+        let (class, dims, name, element) = &vars[0];
+        assert_eq!((*class, dims.clone(), name.as_str()), (MX_DOUBLE_CLASS, vec![3, 2], "frames"));
+        let data = &element[element.len() - 48..];
+        assert_eq!(&data[24..32], &4.0f64.to_le_bytes());
+
+        let (_, dims, name, element) = &vars[1];
+        assert_eq!((dims.clone(), name.as_str()), (vec![3, 1], "t"));
+        assert_eq!(&element[element.len() - 8..], &1.0f64.to_le_bytes());
+
+        let (class, _, name, element) = &vars[2];
+        assert_eq!((*class, name.as_str()), (MX_STRUCT_CLASS, "header"));
+        let names_at = element.windows(11).position(|w| w == b"wfm_version").unwrap();
+        assert_eq!(&element[names_at + 32..names_at + 44], b"num_impl_dim");
+    }
+}
@@ -0,0 +1,34 @@
+// Export Formats
+// Writers that turn a loaded WfmFile into other file formats
+
+mod csv;
+mod mat;
+mod npy;
+mod raw;
+mod stream;
+mod tdms;
+mod wav;
+mod zip;
+#[cfg(feature = "arrow")]
+mod columnar;
+#[cfg(feature = "xlsx")]
+mod xlsx;
+
+pub use csv::{CsvColumn, CsvOptions, FloatFormat};
+pub use raw::{sidecar_path, SampleType};
+pub use stream::StreamFormat;
+pub use wav::{WavFormat, WavOptions, DEFAULT_WAV_RATE};
+#[cfg(feature = "fft")]
+pub(crate) use npy::write_f64_npy;
+#[cfg(feature = "fft")]
+pub(crate) use zip::ZipWriter;
+
+/// Table shape used by the tabular exporters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TableLayout {
+    /// One row per sample of every frame
+    #[default]
+    Long,
+    /// One row per sample index with one column per frame
+    Wide,
+}
@@ -0,0 +1,147 @@
+// NumPy Export
+// .npy and .npz writers for frames x samples data
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use super::zip::ZipWriter;
+use crate::{Result, Waveform, WfmFile};
+
+/// Write an NPY v1.0 header for a C-ordered array
+pub(crate) fn write_npy_header<W: Write>(writer: &mut W, descr: &str, shape: &[usize]) -> io::Result<()> {
+    let shape = match shape {
+        [] => "()".to_string(),
+        [n] => format!("({},)", n),
+        dims => format!("({})", dims.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+    
+    // Magic (6) + version (2) + length (2) + header must be a multiple of 64
+    let padding = 64 - (10 + header.len() + 1) % 64;
+    header.extend(std::iter::repeat_n(' ', padding % 64));
+    header.push('\n');
+    
+    writer.write_all(b"\x93NUMPY\x01\x00")?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())
+}
+
+pub(crate) fn write_f64_npy<W: Write>(writer: &mut W, shape: &[usize], values: &[f64]) -> io::Result<()> {
+    write_npy_header(writer, "<f8", shape)?;
+    for v in values {
+        writer.write_all(&v.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+impl WfmFile {
+    /// Write the scaled data as a frames × samples `float64` NPY array
+    ///
+    /// An `.npy` file holds a single array; use [`write_npz`](Self::write_npz)
+    /// to also store the time axis and scaling metadata.
+    pub fn write_npy<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        let file = File::create(output_file)?;
+        let mut writer = BufWriter::new(file);
+        
+        self.write_frames_npy(&mut writer)?;
+        
+        writer.flush()?;
+        Ok(())
+    }
+    
+    /// Write an NPZ archive with the data, time axis and scaling metadata
+    ///
+    /// The archive contains `frames` (frames × samples `float64`), `raw`
+    /// (frames × samples `int8`, when raw codes are loaded), `t` (the time
+    /// axis) and the scalars `voltage_scale`, `voltage_offset`,
+    /// `acq_time_scale` and `acq_time_start`.
+    pub fn write_npz<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        let file = File::create(output_file)?;
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+        
+        zip.start_entry("frames.npy")?;
+        self.write_frames_npy(&mut zip)?;
+        
+        if self.file_content.raw_frames.len() == self.total_samples() {
+            zip.start_entry("raw.npy")?;
+            write_npy_header(&mut zip, "|i1", &[self.num_frames() as usize, self.samples_per_frame()])?;
+            let raw: Vec<u8> = self.file_content.raw_frames.iter().map(|&v| v as u8).collect();
+            zip.write_all(&raw)?;
+        }
+        
+        zip.start_entry("t.npy")?;
+        write_f64_npy(&mut zip, &[self.samples_per_frame()], &self.time_values())?;
+        
+        let header = &self.file_header;
+        for (name, value) in [
+            ("voltage_scale", header.voltage_scale),
+            ("voltage_offset", header.voltage_offset),
+            ("acq_time_scale", header.acq_time_scale),
+            ("acq_time_start", header.acq_time_start),
+        ] {
+            zip.start_entry(&format!("{}.npy", name))?;
+            write_f64_npy(&mut zip, &[], &[value])?;
+        }
+        
+        zip.finish()?.flush()?;
+        Ok(())
+    }
+    
+    fn write_frames_npy<W: Write>(&self, writer: &mut W) -> Result<()> {
+        write_npy_header(writer, "<f8", &[self.num_frames() as usize, self.samples_per_frame()])?;
+        for i in 0..self.num_frames() {
+            let frame = self.try_frame(i)?;
+            for v in frame.iter() {
+                writer.write_all(&v.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+    
+    fn test_wfm() -> WfmFile {
+        let mut wfm = WfmFile::new();
+        wfm.file_header.num_fastframes = 2;
+        wfm.file_header.full_record_length = 3;
+        wfm.file_header.acq_time_scale = 1e-9;
+        wfm.file_content.raw_frames = vec![1, 2, 3, 4, 5, -6];
+        wfm.file_content.scaled_frames = vec![1.0, 2.0, 3.0, 4.0, 5.0, -6.0];
+        wfm
+    }
+    
+    #[test]
+    fn test_npy_layout() {
+        let out = NamedTempFile::new().unwrap();
+        test_wfm().write_npy(out.path()).unwrap();
+        
+        let bytes = std::fs::read(out.path()).unwrap();
+        let header_len = u16::from_le_bytes([bytes[8], bytes[9]]) as usize;
+        let header = std::str::from_utf8(&bytes[10..10 + header_len]).unwrap();
+        
+        assert_eq!(&bytes[..6], b"\x93NUMPY");
+        assert_eq!((10 + header_len) % 64, 0);
+        assert!(header.contains("'shape': (2, 3)"));
+        assert!(header.ends_with('\n'));
+        assert_eq!(bytes.len(), 10 + header_len + 6 * 8);
+        assert_eq!(&bytes[bytes.len() - 8..], &(-6.0f64).to_le_bytes());
+    }
+    
+    #[test]
+    fn test_npz_entries() {
+        let out = NamedTempFile::new().unwrap();
+        test_wfm().write_npz(out.path()).unwrap();
+        
+        let bytes = std::fs::read(out.path()).unwrap();
+        let entries = bytes.windows(4).filter(|w| w == &0x0201_4b50u32.to_le_bytes()).count();
+        assert_eq!(entries, 7);
+        
+        let raw_pos = bytes.windows(7).position(|w| w == b"raw.npy").unwrap();
+        assert_eq!(&bytes[raw_pos - 30..raw_pos - 26], &0x0403_4b50u32.to_le_bytes());
+    }
+}
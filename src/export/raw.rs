@@ -0,0 +1,377 @@
+// Raw Binary Export
+// Flat little-endian sample files with a JSON sidecar
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{Result, Waveform, WfmContent, WfmError, WfmFile, WfmHeader};
+
+/// Sample type of a raw binary file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SampleType {
+    /// Scaled values as `f32`
+    F32,
+    /// Scaled values as `f64`
+    #[default]
+    F64,
+    /// Raw ADC codes as `i8`; apply the sidecar scale and offset to get volts
+    I8,
+}
+
+impl SampleType {
+    /// Name used in the sidecar and on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            SampleType::F32 => "f32",
+            SampleType::F64 => "f64",
+            SampleType::I8 => "i8",
+        }
+    }
+
+    /// Parse a sample type name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "f32" | "float32" => Some(SampleType::F32),
+            "f64" | "float64" => Some(SampleType::F64),
+            "i8" | "int8" => Some(SampleType::I8),
+            _ => None,
+        }
+    }
+
+    /// Bytes per sample
+    pub fn size(&self) -> usize {
+        match self {
+            SampleType::F32 => 4,
+            SampleType::F64 => 8,
+            SampleType::I8 => 1,
+        }
+    }
+}
+
+/// Path of the JSON sidecar that accompanies a raw binary file
+///
+/// The sidecar name is the data file name with `.json` appended,
+/// e.g. `capture.bin` → `capture.bin.json`.
+pub fn sidecar_path<P: AsRef<Path>>(data_file: P) -> PathBuf {
+    let mut name = data_file.as_ref().as_os_str().to_owned();
+    name.push(".json");
+    PathBuf::from(name)
+}
+
+/// Write the samples of one frame in the given type
+pub(crate) fn write_frame_samples<W: Write>(
+    writer: &mut W,
+    dtype: SampleType,
+    scaled: &[f64],
+    raw: Option<&[i8]>,
+) -> Result<()> {
+    match dtype {
+        SampleType::F32 => for v in scaled {
+            writer.write_all(&(*v as f32).to_le_bytes())?;
+        },
+        SampleType::F64 => for v in scaled {
+            writer.write_all(&v.to_le_bytes())?;
+        },
+        SampleType::I8 => {
+            let raw = raw.ok_or_else(|| WfmError::ParseError(
+                "i8 output requires raw ADC codes, which this data does not have".to_string()
+            ))?;
+            let bytes: Vec<u8> = raw.iter().map(|&v| v as u8).collect();
+            writer.write_all(&bytes)?;
+        }
+    }
+    Ok(())
+}
+
+/// Format an `f64` as a JSON number, or `null` when it is not finite
+fn json_number(v: f64) -> String {
+    if v.is_finite() { format!("{:?}", v) } else { "null".to_string() }
+}
+
+/// Format a JSON string literal, escaping quotes, backslashes and control characters
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Write the JSON sidecar describing a raw binary file
+pub(crate) fn write_sidecar<S: Waveform + ?Sized>(
+    data_file: &Path,
+    source: &S,
+    header: &WfmHeader,
+    dtype: SampleType,
+) -> Result<()> {
+    let (scale, offset) = match dtype {
+        SampleType::I8 => (header.voltage_scale, header.voltage_offset),
+        _ => (1.0, 0.0),
+    };
+
+    // Float data is already scaled; the ADC scaling is kept for reference
+    let fields = [
+        ("format", json_string("wfm_reader raw binary")),
+        ("dtype", json_string(dtype.name())),
+        ("byte_order", json_string("little")),
+        ("num_frames", source.num_frames().to_string()),
+        ("samples_per_frame", source.samples_per_frame().to_string()),
+        ("sample_interval", json_number(source.sample_interval())),
+        ("t0", json_number(source.t0())),
+        ("scale", json_number(scale)),
+        ("offset", json_number(offset)),
+        ("adc_scale", json_number(header.voltage_scale)),
+        ("adc_offset", json_number(header.voltage_offset)),
+        ("vertical_unit", json_string(source.vertical_unit())),
+        ("horizontal_unit", json_string(source.horizontal_unit())),
+    ];
+
+    let body: Vec<String> = fields.iter()
+        .map(|(name, value)| format!("  \"{}\": {}", name, value))
+        .collect();
+    fs::write(sidecar_path(data_file), format!("{{\n{}\n}}\n", body.join(",\n")))?;
+    Ok(())
+}
+
+/// Parse a flat JSON object of string, number and null values
+fn parse_flat_json(text: &str) -> Result<HashMap<String, String>> {
+    let bad = |what: &str| WfmError::ParseError(format!("Invalid sidecar JSON: {}", what));
+
+    let body = text.trim()
+        .strip_prefix('{')
+        .and_then(|t| t.strip_suffix('}'))
+        .ok_or_else(|| bad("expected an object"))?;
+
+    let mut fields = HashMap::new();
+    let mut chars = body.chars().peekable();
+
+    let read_string = |chars: &mut std::iter::Peekable<std::str::Chars>| -> Result<String> {
+        let mut out = String::new();
+        loop {
+            match chars.next().ok_or_else(|| bad("unterminated string"))? {
+                '"' => return Ok(out),
+                '\\' => out.push(match chars.next().ok_or_else(|| bad("unterminated escape"))? {
+                    'n' => '\n',
+                    'r' => '\r',
+                    't' => '\t',
+                    'b' => '\u{8}',
+                    'f' => '\u{c}',
+                    'u' => {
+                        let hex: String = chars.by_ref().take(4).collect();
+                        let code = u32::from_str_radix(&hex, 16).map_err(|_| bad("invalid \\u escape"))?;
+                        char::from_u32(code).ok_or_else(|| bad("invalid \\u escape"))?
+                    }
+                    c @ ('"' | '\\' | '/') => c,
+                    c => return Err(bad(&format!("unknown escape '\\{}'", c))),
+                }),
+                c => out.push(c),
+            }
+        }
+    };
+
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace() || *c == ',') {
+            chars.next();
+        }
+        match chars.next() {
+            None => break,
+            Some('"') => {}
+            Some(c) => return Err(bad(&format!("unexpected '{}'", c))),
+        }
+
+        let key = read_string(&mut chars)?;
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        if chars.next() != Some(':') {
+            return Err(bad("expected ':'"));
+        }
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+
+        let value = if chars.peek() == Some(&'"') {
+            chars.next();
+            read_string(&mut chars)?
+        } else {
+            let mut v = String::new();
+            while chars.peek().is_some_and(|c| *c != ',' && !c.is_whitespace()) {
+                v.push(chars.next().unwrap());
+            }
+            v
+        };
+        fields.insert(key, value);
+    }
+
+    Ok(fields)
+}
+
+impl WfmFile {
+    /// Write all frames as a flat little-endian sample file plus a JSON sidecar
+    ///
+    /// Frames follow each other without padding. The sidecar (see
+    /// [`sidecar_path`]) records frame count, record length, sample interval,
+    /// t0, the scale and offset that turn the stored samples into volts, and
+    /// the capture's ADC scale and offset. `i8` output writes the raw ADC codes and requires
+    /// them to be loaded.
+    pub fn write_raw_binary<P: AsRef<Path>>(&self, output_file: P, dtype: SampleType) -> Result<()> {
+        let file = File::create(&output_file)?;
+        let mut writer = BufWriter::new(file);
+
+        for i in 0..self.num_frames() {
+            let raw = self.raw_frame(i);
+            write_frame_samples(&mut writer, dtype, &self.try_frame(i)?, raw.as_deref())?;
+        }
+        writer.flush()?;
+
+        write_sidecar(output_file.as_ref(), self, &self.file_header, dtype)
+    }
+
+    /// Load a raw binary file and its JSON sidecar written by
+    /// [`write_raw_binary`](Self::write_raw_binary)
+    pub fn read_raw_binary<P: AsRef<Path>>(input_file: P) -> Result<Self> {
+        let sidecar = parse_flat_json(&fs::read_to_string(sidecar_path(&input_file))?)?;
+
+        let get = |name: &str| sidecar.get(name)
+            .ok_or_else(|| WfmError::ParseError(format!("Sidecar is missing '{}'", name)));
+        let number = |name: &str| -> Result<f64> {
+            let v = get(name)?;
+            if v == "null" {
+                return Ok(f64::NAN);
+            }
+            v.parse().map_err(|_| WfmError::ParseError(format!("Sidecar field '{}' is not a number", name)))
+        };
+        let count = |name: &str| -> Result<f64> {
+            let v = number(name)?;
+            if v.is_finite() && v >= 0.0 && v.fract() == 0.0 {
+                Ok(v)
+            } else {
+                Err(WfmError::ParseError(format!("Sidecar field '{}' is not a non-negative integer", name)))
+            }
+        };
+
+        let dtype = SampleType::from_name(get("dtype")?)
+            .ok_or_else(|| WfmError::ParseError(format!("Unknown sample type '{}'", sidecar["dtype"])))?;
+        if sidecar.get("byte_order").is_some_and(|b| b != "little") {
+            return Err(WfmError::ParseError("Only little-endian raw files are supported".to_string()));
+        }
+
+        let num_frames = count("num_frames")? as u32;
+        let record_length = count("samples_per_frame")? as usize;
+        let (scale, offset) = (number("scale")?, number("offset")?);
+
+        let bytes = fs::read(&input_file)?;
+        let expected = num_frames as usize * record_length * dtype.size();
+        if bytes.len() != expected {
+            return Err(WfmError::ParseError(
+                format!("Raw file holds {} bytes, sidecar describes {}", bytes.len(), expected)
+            ));
+        }
+
+        let mut content = WfmContent::default();
+        match dtype {
+            SampleType::F32 => content.scaled_frames = bytes.chunks_exact(4)
+                .map(|c| f32::from_le_bytes(c.try_into().unwrap()) as f64 * scale + offset)
+                .collect(),
+            SampleType::F64 => content.scaled_frames = bytes.chunks_exact(8)
+                .map(|c| f64::from_le_bytes(c.try_into().unwrap()) * scale + offset)
+                .collect(),
+            SampleType::I8 => {
+                content.raw_frames = bytes.iter().map(|&b| b as i8).collect();
+                content.scaled_frames = content.raw_frames.iter()
+                    .map(|&v| v as f64 * scale + offset)
+                    .collect();
+            }
+        }
+
+        let mut file_header = WfmHeader::synthetic(num_frames, record_length,
+                                                   number("sample_interval")?, number("t0")?)?;
+        if sidecar.contains_key("adc_scale") {
+            file_header.voltage_scale = number("adc_scale")?;
+            file_header.voltage_offset = number("adc_offset")?;
+        } else if dtype == SampleType::I8 {
+            file_header.voltage_scale = scale;
+            file_header.voltage_offset = offset;
+        }
+
+        content.vertical_unit = sidecar.get("vertical_unit").filter(|&unit| unit != "V").cloned();
+
+        Ok(WfmFile {
+            file_path: input_file.as_ref().to_string_lossy().to_string(),
+            file_header,
+            file_content: content,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_test_wfm;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_raw_binary_round_trip() {
+        let input = write_test_wfm(3, 20, |f, i| (f as i32 * 7 - i as i32) as i8);
+        let wfm = WfmFile::open(input.path()).unwrap();
+        let dir = TempDir::new().unwrap();
+
+        for dtype in [SampleType::F32, SampleType::F64, SampleType::I8] {
+            let path = dir.path().join(format!("capture.{}", dtype.name()));
+            wfm.write_raw_binary(&path, dtype).unwrap();
+            assert_eq!(fs::metadata(&path).unwrap().len() as usize, 60 * dtype.size());
+
+            let restored = WfmFile::read_raw_binary(&path).unwrap();
+            assert_eq!(restored.file_header.num_fastframes, 3);
+            assert_eq!(restored.file_header.full_record_length, 20);
+            assert_eq!(restored.file_header.acq_time_scale, 1e-9);
+            assert_eq!(restored.file_header.acq_time_start, -100e-9);
+            assert_eq!(restored.file_header.voltage_scale, 0.01);
+            for (a, b) in restored.file_content.scaled_frames.iter().zip(&wfm.file_content.scaled_frames) {
+                assert!((a - b).abs() < 1e-6, "{} != {}", a, b);
+            }
+        }
+
+        let restored = WfmFile::read_raw_binary(dir.path().join("capture.i8")).unwrap();
+        assert_eq!(restored.file_content.raw_frames, wfm.file_content.raw_frames);
+        assert_eq!(restored.file_header.voltage_scale, 0.01);
+
+        // A corrupt frame count or record length is an error, not an empty capture
+        let path = dir.path().join("capture.f64");
+        let sidecar = fs::read_to_string(sidecar_path(&path)).unwrap();
+        for bad in ["null", "-3", "1.5"] {
+            fs::write(sidecar_path(&path), sidecar.replace("\"num_frames\": 3", &format!("\"num_frames\": {}", bad))).unwrap();
+            assert!(WfmFile::read_raw_binary(&path).is_err(), "{}", bad);
+        }
+        fs::write(sidecar_path(&path), sidecar.replace("\"samples_per_frame\": 20", "\"samples_per_frame\": null")).unwrap();
+        assert!(WfmFile::read_raw_binary(&path).is_err());
+    }
+
+    #[test]
+    fn test_flat_json() {
+        let fields = parse_flat_json("{\"a\": \"x\\\"y\", \"b\": -1.5e-9,\n\"c\":null}").unwrap();
+        assert_eq!(fields["a"], "x\"y");
+        assert_eq!(fields["b"], "-1.5e-9");
+        assert_eq!(fields["c"], "null");
+        assert!(parse_flat_json("[1, 2]").is_err());
+
+        let text = "tab\there\nquote\" back\\ bell\u{7} é";
+        let encoded = json_string(text);
+        assert_eq!(encoded, "\"tab\\there\\nquote\\\" back\\\\ bell\\u0007 é\"");
+        assert_eq!(parse_flat_json(&format!("{{\"s\": {}}}", encoded)).unwrap()["s"], text);
+        assert_eq!(parse_flat_json("{\"s\": \"\\u00e9\\/\"}").unwrap()["s"], "é/");
+        assert!(parse_flat_json("{\"s\": \"\\q\"}").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+// Streaming Export
+// Frame-by-frame conversion straight from disk with bounded memory
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use super::csv::{write_csv_table, CsvOptions};
+use super::npy::write_npy_header;
+use super::raw::{write_frame_samples, write_sidecar, SampleType};
+use super::TableLayout;
+use crate::{Result, Waveform, WfmError, WfmReader};
+
+/// Output format of [`WfmReader::convert`]
+#[derive(Clone, Debug)]
+pub enum StreamFormat {
+    /// CSV in the long layout
+    Csv(CsvOptions),
+    /// Flat little-endian samples, frame after frame, with a JSON sidecar
+    Binary(SampleType),
+    /// Frames × samples `float64` NPY array
+    Npy,
+}
+
+impl WfmReader {
+    /// Convert the file one frame at a time and return the number of samples written
+    ///
+    /// Only a single frame is held in memory, regardless of file size. The
+    /// wide CSV layout needs every frame at once and is rejected; load the
+    /// file with [`WfmFile::open`](crate::WfmFile::open) for it instead.
+    pub fn convert<P: AsRef<Path>>(&self, output_file: P, format: &StreamFormat) -> Result<usize> {
+        if let StreamFormat::Csv(options) = format {
+            if options.layout == TableLayout::Wide {
+                return Err(WfmError::ParseError(
+                    "The wide CSV layout cannot be streamed".to_string()
+                ));
+            }
+        }
+
+        let file = File::create(&output_file)?;
+        let mut writer = BufWriter::new(file);
+
+        match format {
+            StreamFormat::Csv(options) => {
+                write_csv_table(self, Some((&self.file_path, &self.file_header)), &mut writer, options)?;
+            }
+            StreamFormat::Binary(dtype) => {
+                for i in 0..self.num_frames() {
+                    let raw = self.read_raw_frame(i)?;
+                    let scaled: Vec<f64> = raw.iter().map(|&v| self.file_header.scaled_value(v)).collect();
+                    write_frame_samples(&mut writer, *dtype, &scaled, Some(&raw))?;
+                }
+            }
+            StreamFormat::Npy => {
+                write_npy_header(&mut writer, "<f8", &[self.num_frames() as usize, self.samples_per_frame()])?;
+                for i in 0..self.num_frames() {
+                    for v in self.read_frame(i)? {
+                        writer.write_all(&v.to_le_bytes())?;
+                    }
+                }
+            }
+        }
+
+        writer.flush()?;
+
+        if let StreamFormat::Binary(dtype) = format {
+            write_sidecar(output_file.as_ref(), self, &self.file_header, *dtype)?;
+        }
+
+        let frames = match format {
+            StreamFormat::Csv(options) => options.frames.as_ref().map_or(self.num_frames(), |r| r.end - r.start),
+            _ => self.num_frames(),
+        };
+        Ok(frames as usize * self.samples_per_frame())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_test_wfm;
+    use crate::WfmFile;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_streaming_matches_in_memory() {
+        let input = write_test_wfm(4, 50, |f, i| (f * 10 + i) as i8);
+        let reader = WfmReader::open(input.path()).unwrap();
+        let wfm = WfmFile::open(input.path()).unwrap();
+
+        let streamed = NamedTempFile::new().unwrap();
+        let loaded = NamedTempFile::new().unwrap();
+
+        let options = CsvOptions::new().metadata_header(true);
+        assert_eq!(reader.convert(streamed.path(), &StreamFormat::Csv(options.clone())).unwrap(), 200);
+        wfm.write_csv_with(loaded.path(), &options).unwrap();
+        assert_eq!(std::fs::read(streamed.path()).unwrap(), std::fs::read(loaded.path()).unwrap());
+
+        reader.convert(streamed.path(), &StreamFormat::Npy).unwrap();
+        wfm.write_npy(loaded.path()).unwrap();
+        assert_eq!(std::fs::read(streamed.path()).unwrap(), std::fs::read(loaded.path()).unwrap());
+
+        reader.convert(streamed.path(), &StreamFormat::Binary(SampleType::F64)).unwrap();
+        let bytes = std::fs::read(streamed.path()).unwrap();
+        assert_eq!(bytes.len(), 200 * 8);
+        assert_eq!(&bytes[8 * 51..8 * 52], &wfm.get_frame(1).unwrap()[1].to_le_bytes());
+        
+        let restored = WfmFile::read_raw_binary(streamed.path()).unwrap();
+        assert_eq!(restored.file_content.scaled_frames, wfm.file_content.scaled_frames);
+        std::fs::remove_file(crate::sidecar_path(streamed.path())).ok();
+    }
+
+    #[test]
+    fn test_wide_csv_rejected() {
+        let input = write_test_wfm(2, 10, |_, _| 0);
+        let reader = WfmReader::open(input.path()).unwrap();
+        let out = NamedTempFile::new().unwrap();
+        assert!(reader.convert(out.path(), &StreamFormat::Csv(CsvOptions::wide())).is_err());
+    }
+}
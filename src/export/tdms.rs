@@ -0,0 +1,233 @@
+// TDMS Export
+// NI TDMS 2.0 writer for LabVIEW and DIAdem, no NI software required
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::{FrameTimestamp, HeaderValue, Result, Waveform, WfmFile};
+
+// Data types
+const TDS_TYPE_I32: u32 = 0x03;
+const TDS_TYPE_I64: u32 = 0x04;
+const TDS_TYPE_DOUBLE: u32 = 0x0a;
+const TDS_TYPE_STRING: u32 = 0x20;
+const TDS_TYPE_BOOLEAN: u32 = 0x21;
+const TDS_TYPE_TIMESTAMP: u32 = 0x44;
+
+// Table of contents flags
+const TOC_META_DATA: u32 = 1 << 1;
+const TOC_NEW_OBJ_LIST: u32 = 1 << 2;
+const TOC_RAW_DATA: u32 = 1 << 3;
+
+const TDMS_VERSION: u32 = 4713;
+
+/// Raw data index marking an object without data in the segment
+const NO_RAW_DATA: u32 = 0xffff_ffff;
+
+/// Seconds between the TDMS epoch (1904-01-01) and the Unix epoch
+const TDMS_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// A property value in the metadata of a TDMS object
+enum Property {
+    I32(i32),
+    I64(i64),
+    Boolean(bool),
+    Double(f64),
+    Text(String),
+    /// Seconds since the Unix epoch, split into whole and fractional seconds
+    Timestamp(i64, f64),
+}
+
+impl From<HeaderValue> for Property {
+    fn from(value: HeaderValue) -> Self {
+        match value {
+            HeaderValue::Text(v) => Property::Text(v),
+            HeaderValue::Int(v) => Property::I64(v),
+            HeaderValue::Float(v) => Property::Double(v),
+            HeaderValue::Bool(v) => Property::Boolean(v),
+        }
+    }
+}
+
+fn put_string(buf: &mut Vec<u8>, s: &str) {
+    buf.extend((s.len() as u32).to_le_bytes());
+    buf.extend(s.as_bytes());
+}
+
+fn put_property(buf: &mut Vec<u8>, name: &str, value: &Property) {
+    put_string(buf, name);
+    match value {
+        Property::I32(v) => {
+            buf.extend(TDS_TYPE_I32.to_le_bytes());
+            buf.extend(v.to_le_bytes());
+        }
+        Property::I64(v) => {
+            buf.extend(TDS_TYPE_I64.to_le_bytes());
+            buf.extend(v.to_le_bytes());
+        }
+        Property::Boolean(v) => {
+            buf.extend(TDS_TYPE_BOOLEAN.to_le_bytes());
+            buf.push(*v as u8);
+        }
+        Property::Double(v) => {
+            buf.extend(TDS_TYPE_DOUBLE.to_le_bytes());
+            buf.extend(v.to_le_bytes());
+        }
+        Property::Text(v) => {
+            buf.extend(TDS_TYPE_STRING.to_le_bytes());
+            put_string(buf, v);
+        }
+        Property::Timestamp(seconds, frac) => {
+            // Fractions of 2^-64 s followed by whole seconds since 1904
+            let whole = frac.floor();
+            let fraction = ((frac - whole) * 2f64.powi(64)) as u64;
+            buf.extend(TDS_TYPE_TIMESTAMP.to_le_bytes());
+            buf.extend(fraction.to_le_bytes());
+            buf.extend((seconds + whole as i64 + TDMS_EPOCH_OFFSET).to_le_bytes());
+        }
+    }
+}
+
+/// Write an object path, its raw data index and its properties
+fn put_object(buf: &mut Vec<u8>, path: &str, values: Option<u64>, properties: &[(&str, Property)]) {
+    put_string(buf, path);
+    match values {
+        Some(count) => {
+            buf.extend(20u32.to_le_bytes());
+            buf.extend(TDS_TYPE_DOUBLE.to_le_bytes());
+            buf.extend(1u32.to_le_bytes());
+            buf.extend(count.to_le_bytes());
+        }
+        None => buf.extend(NO_RAW_DATA.to_le_bytes()),
+    }
+    buf.extend((properties.len() as u32).to_le_bytes());
+    for (name, value) in properties {
+        put_property(buf, name, value);
+    }
+}
+
+/// Quote a group or channel name for use in an object path
+fn path_name(name: &str) -> String {
+    format!("'{}'", name.replace('\'', "''"))
+}
+
+impl WfmFile {
+    /// Write the data as an NI TDMS file
+    ///
+    /// The file holds one group named after the source file and one `double`
+    /// channel per frame. Every channel carries the waveform properties
+    /// `wf_increment`, `wf_start_offset`, `wf_samples` and `unit_string`, and
+    /// when trigger times were loaded also `trigger_time`, `trigger_offset`
+    /// and `wf_start_time` (the absolute time of the first sample). Header
+    /// fields are stored as group properties.
+    pub fn write_tdms<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        let group = Path::new(&self.file_path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .filter(|s| !s.is_empty())
+            .unwrap_or_else(|| "Waveform".to_string());
+        let group_path = format!("/{}", path_name(&group));
+        let samples = self.samples_per_frame() as u64;
+
+        let mut meta = Vec::new();
+        meta.extend((self.num_frames() + 2).to_le_bytes());
+
+        put_object(&mut meta, "/", None, &[
+            ("name", Property::Text(group.clone())),
+            ("source_file", Property::Text(self.file_path.clone())),
+        ]);
+
+        let header_properties: Vec<(&str, Property)> = self.file_header.fields()
+            .into_iter()
+            .map(|(name, value)| (name, value.into()))
+            .collect();
+        put_object(&mut meta, &group_path, None, &header_properties);
+
+        for i in 0..self.num_frames() {
+            let mut properties = vec![
+                ("wf_increment", Property::Double(self.sample_interval())),
+                ("wf_start_offset", Property::Double(self.t0())),
+                ("wf_samples", Property::I32(samples.min(i32::MAX as u64) as i32)),
+                ("wf_xname", Property::Text("Time".to_string())),
+                ("wf_xunit_string", Property::Text(self.horizontal_unit().to_string())),
+                ("unit_string", Property::Text(self.vertical_unit().to_string())),
+            ];
+            if let Some(&FrameTimestamp { gmt_seconds, frac_seconds, trigger_offset }) =
+                self.file_content.frame_timestamps.get(i as usize)
+            {
+                let seconds = gmt_seconds as i64;
+                properties.push(("trigger_time", Property::Timestamp(seconds, frac_seconds)));
+                properties.push(("trigger_offset", Property::Double(trigger_offset)));
+                properties.push(("wf_start_time", Property::Timestamp(seconds, frac_seconds + self.t0())));
+            }
+
+            let channel_path = format!("{}/{}", group_path, path_name(&format!("Frame {}", i)));
+            put_object(&mut meta, &channel_path, Some(samples), &properties);
+        }
+
+        let raw_len = self.num_frames() as u64 * samples * 8;
+
+        let file = File::create(output_file)?;
+        let mut writer = BufWriter::new(file);
+
+        // Segment lead-in
+        writer.write_all(b"TDSm")?;
+        writer.write_all(&(TOC_META_DATA | TOC_NEW_OBJ_LIST | TOC_RAW_DATA).to_le_bytes())?;
+        writer.write_all(&TDMS_VERSION.to_le_bytes())?;
+        writer.write_all(&(meta.len() as u64 + raw_len).to_le_bytes())?;      // next segment offset
+        writer.write_all(&(meta.len() as u64).to_le_bytes())?;                // raw data offset
+        writer.write_all(&meta)?;
+
+        // Channels are not interleaved, so the frames follow each other
+        for i in 0..self.num_frames() {
+            for v in self.try_frame(i)?.iter() {
+                writer.write_all(&v.to_le_bytes())?;
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_test_wfm;
+    use tempfile::NamedTempFile;
+
+    fn find(bytes: &[u8], needle: &[u8]) -> usize {
+        bytes.windows(needle.len()).position(|w| w == needle).unwrap()
+    }
+
+    #[test]
+    fn test_write_tdms() {
+        let input = write_test_wfm(3, 10, |f, i| (f * 10 + i) as i8);
+        let wfm = WfmFile::open(input.path()).unwrap();
+        let out = NamedTempFile::new().unwrap();
+        wfm.write_tdms(out.path()).unwrap();
+        let bytes = std::fs::read(out.path()).unwrap();
+
+        assert_eq!(&bytes[..4], b"TDSm");
+        let next_segment = u64::from_le_bytes(bytes[12..20].try_into().unwrap()) as usize;
+        let raw_offset = u64::from_le_bytes(bytes[20..28].try_into().unwrap()) as usize;
+        assert_eq!(bytes.len(), 28 + next_segment);
+        assert_eq!(next_segment - raw_offset, 3 * 10 * 8);
+
+        // Raw data of the last channel ends the file
+        let last = f64::from_le_bytes(bytes[bytes.len() - 8..].try_into().unwrap());
+        assert_eq!(last, wfm.get_frame(2).unwrap()[9]);
+
+        let channel = find(&bytes, b"/'Frame 2'");
+        let increment = channel + find(&bytes[channel..], b"wf_increment") + 12;
+        assert_eq!(u32::from_le_bytes(bytes[increment..increment + 4].try_into().unwrap()), TDS_TYPE_DOUBLE);
+        assert_eq!(f64::from_le_bytes(bytes[increment + 4..increment + 12].try_into().unwrap()), 1e-9);
+
+        let trigger = channel + find(&bytes[channel..], b"trigger_time") + 12;
+        assert_eq!(u32::from_le_bytes(bytes[trigger..trigger + 4].try_into().unwrap()), TDS_TYPE_TIMESTAMP);
+        assert_eq!(u64::from_le_bytes(bytes[trigger + 4..trigger + 12].try_into().unwrap()), 1 << 62);
+        assert_eq!(i64::from_le_bytes(bytes[trigger + 12..trigger + 20].try_into().unwrap()),
+                   1_700_000_002 + TDMS_EPOCH_OFFSET);
+    }
+}
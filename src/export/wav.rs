@@ -0,0 +1,240 @@
+// WAV Export
+// Frames as audio for listening or inspection in audio editors
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::{Result, Waveform, WfmError, WfmFile};
+
+/// Common audio rate to set as the playback rate when the capture's own rate
+/// is not usable
+pub const DEFAULT_WAV_RATE: u32 = 48_000;
+
+/// Sample encoding of a WAV file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WavFormat {
+    /// 16-bit signed PCM
+    #[default]
+    Pcm16,
+    /// 32-bit signed PCM
+    Pcm32,
+    /// 32-bit IEEE float
+    Float32,
+}
+
+impl WavFormat {
+    fn bits(&self) -> u16 {
+        match self {
+            WavFormat::Pcm16 => 16,
+            WavFormat::Pcm32 | WavFormat::Float32 => 32,
+        }
+    }
+}
+
+/// Options for [`WfmFile::write_wav`]
+#[derive(Clone, Debug, Default)]
+pub struct WavOptions {
+    /// Frame to export, or all frames concatenated when `None`
+    pub frame: Option<u32>,
+    /// Samples of silence inserted between concatenated frames
+    pub gap_samples: usize,
+    /// Sample encoding
+    pub format: WavFormat,
+    /// Playback rate in Hz; `None` uses the true capture rate from
+    /// `acq_time_scale`, and writing fails if that does not fit a WAV header
+    ///
+    /// Many audio tools reject rates above 384 kHz, so ultrasonic captures
+    /// may need a lower rate such as [`DEFAULT_WAV_RATE`] to play at all.
+    pub sample_rate: Option<u32>,
+    /// Scale the exported samples so the largest magnitude reaches full scale
+    ///
+    /// Without normalisation values are written in volts, so ±1 V is full
+    /// scale and PCM output clips beyond it.
+    pub normalize: bool,
+}
+
+impl WavOptions {
+    /// All frames as 16-bit PCM at the true capture rate
+    pub fn new() -> Self {
+        WavOptions::default()
+    }
+
+    /// Export a single frame
+    pub fn frame(mut self, frame: u32) -> Self {
+        self.frame = Some(frame);
+        self
+    }
+
+    /// Insert silence between concatenated frames
+    pub fn gap_samples(mut self, gap_samples: usize) -> Self {
+        self.gap_samples = gap_samples;
+        self
+    }
+
+    /// Set the sample encoding
+    pub fn format(mut self, format: WavFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set the playback rate in Hz
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// Enable or disable auto-normalisation
+    pub fn normalize(mut self, normalize: bool) -> Self {
+        self.normalize = normalize;
+        self
+    }
+}
+
+impl WfmFile {
+    /// Write one frame, or all frames concatenated, as a mono WAV file
+    pub fn write_wav<P: AsRef<Path>>(&self, output_file: P, options: &WavOptions) -> Result<()> {
+        let frames = match options.frame {
+            Some(i) if i >= self.num_frames() => {
+                return Err(WfmError::InvalidFrameRange(i, i + 1, self.num_frames()));
+            }
+            Some(i) => i..i + 1,
+            None => 0..self.num_frames(),
+        };
+
+        let bytes_per_sample = (options.format.bits() / 8) as usize;
+        let sample_rate = match options.sample_rate {
+            Some(rate) => rate,
+            None => {
+                let rate = (1.0 / self.sample_interval()).round();
+                if !(rate.is_finite() && rate >= 1.0 && rate * bytes_per_sample as f64 <= u32::MAX as f64) {
+                    return Err(WfmError::ParseError(
+                        format!("Capture rate of {} Hz does not fit a WAV header; set the sample rate explicitly",
+                                1.0 / self.sample_interval())
+                    ));
+                }
+                rate as u32
+            }
+        };
+        let byte_rate = sample_rate.checked_mul(bytes_per_sample as u32)
+            .filter(|_| sample_rate > 0)
+            .ok_or_else(|| WfmError::ParseError(format!("Invalid WAV sample rate {} Hz", sample_rate)))?;
+
+        let gain = if options.normalize {
+            let mut peak = 0.0f64;
+            for i in frames.clone() {
+                peak = self.try_frame(i)?.iter().fold(peak, |p, v| p.max(v.abs()));
+            }
+            if peak > 0.0 { 1.0 / peak } else { 1.0 }
+        } else {
+            1.0
+        };
+
+        let num_samples = frames.len() * self.samples_per_frame() +
+                          frames.len().saturating_sub(1) * options.gap_samples;
+        let data_len = num_samples * bytes_per_sample;
+        if data_len > (u32::MAX - 36) as usize {
+            return Err(WfmError::ParseError("WAV data exceeds the 4 GiB limit".to_string()));
+        }
+
+        let file = File::create(output_file)?;
+        let mut writer = BufWriter::new(file);
+
+        // RIFF header and format chunk
+        let format_tag: u16 = if options.format == WavFormat::Float32 { 3 } else { 1 };
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(36 + data_len as u32).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        writer.write_all(&format_tag.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;                                   // mono
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&byte_rate.to_le_bytes())?;
+        writer.write_all(&(bytes_per_sample as u16).to_le_bytes())?;              // block align
+        writer.write_all(&options.format.bits().to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&(data_len as u32).to_le_bytes())?;
+
+        let write_sample = |w: &mut BufWriter<File>, v: f64| -> Result<()> {
+            let v = v * gain;
+            match options.format {
+                WavFormat::Pcm16 => w.write_all(&((v.clamp(-1.0, 1.0) * i16::MAX as f64).round() as i16).to_le_bytes())?,
+                WavFormat::Pcm32 => w.write_all(&((v.clamp(-1.0, 1.0) * i32::MAX as f64).round() as i32).to_le_bytes())?,
+                WavFormat::Float32 => w.write_all(&(v as f32).to_le_bytes())?,
+            }
+            Ok(())
+        };
+
+        for (n, i) in frames.enumerate() {
+            if n > 0 {
+                for _ in 0..options.gap_samples {
+                    write_sample(&mut writer, 0.0)?;
+                }
+            }
+            for &v in self.try_frame(i)?.iter() {
+                write_sample(&mut writer, v)?;
+            }
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    fn test_wfm() -> WfmFile {
+        WfmFile::from_samples(4, 1.0 / 96_000.0, 0.0, vec![0.0, 0.25, -0.5, 0.1, 0.2, 0.2, 0.2, 0.2]).unwrap()
+    }
+
+    #[test]
+    fn test_wav_header_and_gap() {
+        let out = NamedTempFile::new().unwrap();
+        test_wfm().write_wav(out.path(), &WavOptions::new().gap_samples(2)).unwrap();
+        let bytes = std::fs::read(out.path()).unwrap();
+
+        assert_eq!(&bytes[..4], b"RIFF");
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), 96_000);
+        assert_eq!(u32::from_le_bytes(bytes[40..44].try_into().unwrap()), 2 * 10);
+        assert_eq!(bytes.len(), 44 + 20);
+
+        let sample = |i: usize| i16::from_le_bytes([bytes[44 + 2 * i], bytes[45 + 2 * i]]);
+        assert_eq!(sample(2), -16384);
+        assert_eq!(sample(4), 0);
+        assert_eq!(sample(6), 6553);
+
+        // Ultrasonic captures keep their true rate
+        let fast = WfmFile::from_samples(4, 1e-6, 0.0, vec![0.0; 4]).unwrap();
+        fast.write_wav(out.path(), &WavOptions::new()).unwrap();
+        let bytes = std::fs::read(out.path()).unwrap();
+        assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), 1_000_000);
+
+        // Rates beyond the header need an explicit playback rate
+        let gigahertz = WfmFile::from_samples(4, 1e-10, 0.0, vec![0.0; 4]).unwrap();
+        assert!(gigahertz.write_wav(out.path(), &WavOptions::new()).is_err());
+        gigahertz.write_wav(out.path(), &WavOptions::new().sample_rate(DEFAULT_WAV_RATE)).unwrap();
+    }
+
+    #[test]
+    fn test_wav_normalized_float_frame() {
+        let out = NamedTempFile::new().unwrap();
+        let options = WavOptions::new()
+            .frame(0)
+            .format(WavFormat::Float32)
+            .sample_rate(44_100)
+            .normalize(true);
+        test_wfm().write_wav(out.path(), &options).unwrap();
+        let bytes = std::fs::read(out.path()).unwrap();
+
+        assert_eq!(u16::from_le_bytes([bytes[20], bytes[21]]), 3);
+        assert_eq!(u32::from_le_bytes(bytes[24..28].try_into().unwrap()), 44_100);
+        let sample = |i: usize| f32::from_le_bytes(bytes[44 + 4 * i..48 + 4 * i].try_into().unwrap());
+        assert_eq!(sample(1), 0.5);
+        assert_eq!(sample(2), -1.0);
+        assert_eq!(bytes.len(), 44 + 16);
+    }
+}
@@ -0,0 +1,134 @@
+// Excel Export
+// Workbook with frames in columns and a metadata sheet (requires the `xlsx` feature)
+
+use std::ops::Range;
+use std::path::Path;
+
+use rust_xlsxwriter::{Format, Workbook, Worksheet};
+
+use crate::{HeaderValue, Result, Waveform, WfmFile};
+
+/// Rows per worksheet in Excel, including the header row
+const MAX_ROWS: usize = 1_048_576;
+
+/// Columns per worksheet in Excel, including the time column
+const MAX_COLS: usize = 16_384;
+
+/// Frame and sample ranges of each data sheet
+///
+/// Every sheet holds one header row and one time column, so at most
+/// `max_rows - 1` samples and `max_cols - 1` frames fit on a sheet.
+fn sheet_blocks(frames: u32, samples: usize, max_rows: usize, max_cols: usize) -> Vec<(Range<u32>, Range<usize>)> {
+    let frames_per_sheet = (max_cols - 1) as u32;
+    let samples_per_sheet = max_rows - 1;
+
+    let mut blocks = Vec::new();
+    for first_frame in (0..frames.max(1)).step_by(frames_per_sheet as usize) {
+        let frame_range = first_frame..(first_frame + frames_per_sheet).min(frames);
+        for first_sample in (0..samples.max(1)).step_by(samples_per_sheet) {
+            blocks.push((frame_range.clone(), first_sample..(first_sample + samples_per_sheet).min(samples)));
+        }
+    }
+    blocks
+}
+
+impl WfmFile {
+    /// Write the data as an Excel workbook
+    ///
+    /// Each data sheet has a time column followed by one column per frame.
+    /// Captures beyond Excel's limit of 1,048,576 rows or 16,384 columns are
+    /// split across sheets named `Data 1`, `Data 2`, … A final `Metadata`
+    /// sheet lists the source file and every header field.
+    pub fn write_xlsx<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        let mut workbook = Workbook::new();
+        let bold = Format::new().set_bold();
+
+        let time_values = self.time_values();
+        let blocks = sheet_blocks(self.num_frames(), self.samples_per_frame(), MAX_ROWS, MAX_COLS);
+        let time_header = format!("Time ({})", self.horizontal_unit());
+
+        for (n, (frames, samples)) in blocks.iter().enumerate() {
+            let sheet = workbook.add_worksheet();
+            if blocks.len() == 1 {
+                sheet.set_name("Data")?;
+            } else {
+                sheet.set_name(format!("Data {}", n + 1))?;
+            }
+
+            sheet.write_string_with_format(0, 0, &time_header, &bold)?;
+            for (row, t) in time_values[samples.clone()].iter().enumerate() {
+                sheet.write_number(row as u32 + 1, 0, *t)?;
+            }
+
+            for (col, i) in frames.clone().enumerate() {
+                let col = col as u16 + 1;
+                sheet.write_string_with_format(0, col, format!("Frame {} ({})", i, self.vertical_unit()), &bold)?;
+                for (row, v) in self.try_frame(i)?[samples.clone()].iter().enumerate() {
+                    sheet.write_number(row as u32 + 1, col, *v)?;
+                }
+            }
+
+            sheet.set_column_width(0, 14)?;
+            sheet.set_freeze_panes(1, 1)?;
+        }
+
+        self.write_metadata_sheet(workbook.add_worksheet(), &bold)?;
+
+        workbook.save(output_file)?;
+        Ok(())
+    }
+
+    fn write_metadata_sheet(&self, sheet: &mut Worksheet, bold: &Format) -> Result<()> {
+        sheet.set_name("Metadata")?;
+        sheet.write_string_with_format(0, 0, "Field", bold)?;
+        sheet.write_string_with_format(0, 1, "Value", bold)?;
+        sheet.write_string(1, 0, "file_path")?;
+        sheet.write_string(1, 1, &self.file_path)?;
+
+        for (row, (name, value)) in self.file_header.fields().into_iter().enumerate() {
+            let row = row as u32 + 2;
+            sheet.write_string(row, 0, name)?;
+            match value {
+                HeaderValue::Text(v) => sheet.write_string(row, 1, v)?,
+                HeaderValue::Int(v) => sheet.write_number(row, 1, v as f64)?,
+                HeaderValue::Float(v) => sheet.write_number(row, 1, v)?,
+                HeaderValue::Bool(v) => sheet.write_boolean(row, 1, v)?,
+            };
+        }
+
+        sheet.set_column_width(0, 22)?;
+        sheet.set_column_width(1, 24)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_sheet_blocks_split() {
+        assert_eq!(sheet_blocks(4, 100, MAX_ROWS, MAX_COLS), vec![(0..4, 0..100)]);
+        assert_eq!(sheet_blocks(5, 7, 4, 3), vec![
+            (0..2, 0..3), (0..2, 3..6), (0..2, 6..7),
+            (2..4, 0..3), (2..4, 3..6), (2..4, 6..7),
+            (4..5, 0..3), (4..5, 3..6), (4..5, 6..7),
+        ]);
+    }
+
+    #[test]
+    fn test_write_xlsx() {
+        let wfm = WfmFile::from_samples(3, 1e-6, 0.0, vec![0.0, 0.5, 1.0, -1.0, -0.5, 0.0]).unwrap();
+        let out = NamedTempFile::new().unwrap();
+        wfm.write_xlsx(out.path()).unwrap();
+
+        // Entry names are stored uncompressed in the zip headers
+        let bytes = std::fs::read(out.path()).unwrap();
+        assert_eq!(&bytes[..2], b"PK");
+        let contains = |name: &[u8]| bytes.windows(name.len()).any(|w| w == name);
+        assert!(contains(b"xl/worksheets/sheet1.xml"));
+        assert!(contains(b"xl/worksheets/sheet2.xml"));
+        assert!(!contains(b"xl/worksheets/sheet3.xml"));
+    }
+}
@@ -0,0 +1,174 @@
+// Minimal ZIP Writer
+// Stored (uncompressed) entries only, enough for NumPy .npz archives
+
+use std::io::{self, Seek, Write};
+
+use crate::{Result, WfmError};
+
+/// CRC-32 (IEEE) lookup table
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+static CRC_TABLE: [u32; 256] = crc_table();
+
+struct Entry {
+    name: String,
+    offset: u64,
+    crc: u32,
+    size: u64,
+}
+
+/// Writes a ZIP archive of stored entries to a seekable sink
+///
+/// Each entry is streamed: the local header is written first and patched
+/// with the CRC and size once the entry is finished.
+pub(crate) struct ZipWriter<W: Write + Seek> {
+    inner: W,
+    entries: Vec<Entry>,
+    current: Option<Entry>,
+}
+
+impl<W: Write + Seek> ZipWriter<W> {
+    pub fn new(inner: W) -> Self {
+        ZipWriter { inner, entries: Vec::new(), current: None }
+    }
+    
+    /// Begin a new entry; subsequent writes go into it
+    pub fn start_entry(&mut self, name: &str) -> Result<()> {
+        self.finish_entry()?;
+        
+        let offset = self.inner.stream_position()?;
+        self.write_local_header(name, 0, 0)?;
+        self.current = Some(Entry { name: name.to_string(), offset, crc: !0, size: 0 });
+        Ok(())
+    }
+    
+    fn write_local_header(&mut self, name: &str, crc: u32, size: u32) -> io::Result<()> {
+        let w = &mut self.inner;
+        w.write_all(&0x0403_4b50u32.to_le_bytes())?;
+        w.write_all(&20u16.to_le_bytes())?;            // version needed
+        w.write_all(&0u16.to_le_bytes())?;             // flags
+        w.write_all(&0u16.to_le_bytes())?;             // stored
+        w.write_all(&0u16.to_le_bytes())?;             // mod time
+        w.write_all(&0x21u16.to_le_bytes())?;          // mod date (1980-01-01)
+        w.write_all(&crc.to_le_bytes())?;
+        w.write_all(&size.to_le_bytes())?;             // compressed size
+        w.write_all(&size.to_le_bytes())?;             // uncompressed size
+        w.write_all(&(name.len() as u16).to_le_bytes())?;
+        w.write_all(&0u16.to_le_bytes())?;             // extra length
+        w.write_all(name.as_bytes())
+    }
+    
+    fn finish_entry(&mut self) -> Result<()> {
+        let Some(mut entry) = self.current.take() else {
+            return Ok(());
+        };
+        entry.crc = !entry.crc;
+        
+        if entry.size > u32::MAX as u64 {
+            return Err(WfmError::ParseError(format!(
+                "Archive entry '{}' exceeds 4 GiB; write it as a separate file instead",
+                entry.name
+            )));
+        }
+        
+        let end = self.inner.stream_position()?;
+        self.inner.seek(io::SeekFrom::Start(entry.offset))?;
+        self.write_local_header(&entry.name, entry.crc, entry.size as u32)?;
+        self.inner.seek(io::SeekFrom::Start(end))?;
+        
+        self.entries.push(entry);
+        Ok(())
+    }
+    
+    /// Write the central directory and return the underlying sink
+    pub fn finish(mut self) -> Result<W> {
+        self.finish_entry()?;
+        
+        let dir_offset = self.inner.stream_position()?;
+        let w = &mut self.inner;
+        for entry in &self.entries {
+            w.write_all(&0x0201_4b50u32.to_le_bytes())?;
+            w.write_all(&20u16.to_le_bytes())?;        // version made by
+            w.write_all(&20u16.to_le_bytes())?;        // version needed
+            w.write_all(&0u16.to_le_bytes())?;         // flags
+            w.write_all(&0u16.to_le_bytes())?;         // stored
+            w.write_all(&0u16.to_le_bytes())?;         // mod time
+            w.write_all(&0x21u16.to_le_bytes())?;      // mod date
+            w.write_all(&entry.crc.to_le_bytes())?;
+            w.write_all(&(entry.size as u32).to_le_bytes())?;
+            w.write_all(&(entry.size as u32).to_le_bytes())?;
+            w.write_all(&(entry.name.len() as u16).to_le_bytes())?;
+            w.write_all(&[0u8; 12])?;                  // extra, comment, disk, attributes
+            w.write_all(&(entry.offset as u32).to_le_bytes())?;
+            w.write_all(entry.name.as_bytes())?;
+        }
+        let dir_end = w.stream_position()?;
+        
+        if dir_end > u32::MAX as u64 {
+            return Err(WfmError::ParseError("Archive exceeds 4 GiB".to_string()));
+        }
+        
+        w.write_all(&0x0605_4b50u32.to_le_bytes())?;
+        w.write_all(&[0u8; 4])?;                       // disk numbers
+        w.write_all(&(self.entries.len() as u16).to_le_bytes())?;
+        w.write_all(&(self.entries.len() as u16).to_le_bytes())?;
+        w.write_all(&((dir_end - dir_offset) as u32).to_le_bytes())?;
+        w.write_all(&(dir_offset as u32).to_le_bytes())?;
+        w.write_all(&0u16.to_le_bytes())?;             // comment length
+        w.flush()?;
+        
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write + Seek> Write for ZipWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let entry = self.current.as_mut()
+            .ok_or_else(|| io::Error::other("no archive entry started"))?;
+        let n = self.inner.write(buf)?;
+        for &b in &buf[..n] {
+            entry.crc = CRC_TABLE[((entry.crc ^ b as u32) & 0xff) as usize] ^ (entry.crc >> 8);
+        }
+        entry.size += n as u64;
+        Ok(n)
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    
+    #[test]
+    fn test_stored_archive_layout() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_entry("a.txt").unwrap();
+        zip.write_all(b"123456789").unwrap();
+        let bytes = zip.finish().unwrap().into_inner();
+        
+        // CRC-32 check value of "123456789"
+        assert_eq!(&bytes[14..18], &0xcbf4_3926u32.to_le_bytes());
+        assert_eq!(&bytes[18..22], &9u32.to_le_bytes());
+        assert_eq!(&bytes[30..35], b"a.txt");
+        assert_eq!(&bytes[35..44], b"123456789");
+        assert_eq!(&bytes[bytes.len() - 22..bytes.len() - 18], &0x0605_4b50u32.to_le_bytes());
+    }
+}
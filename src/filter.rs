@@ -0,0 +1,625 @@
+// Digital Filters
+// IIR biquad cascades, FIR designs and smoothing for frames
+
+use std::f64::consts::PI;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::{Result, Waveform, WfmError, WfmFile};
+
+/// Pass or stop band of a filter design, in Hz
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Band {
+    LowPass(f64),
+    HighPass(f64),
+    /// Lower and upper edge of the pass band
+    BandPass(f64, f64),
+    /// Lower and upper edge of the stop band
+    BandStop(f64, f64),
+}
+
+impl Band {
+    /// Band edges as fractions of the sample rate, checked against Nyquist
+    fn normalized(&self, sample_rate: f64) -> Result<Vec<f64>> {
+        let edges = match *self {
+            Band::LowPass(f) | Band::HighPass(f) => vec![f],
+            Band::BandPass(low, high) | Band::BandStop(low, high) => vec![low, high],
+        };
+        if edges.windows(2).any(|w| w[0] >= w[1]) {
+            return Err(WfmError::ParseError(
+                format!("Band edges {} Hz and {} Hz are not in increasing order", edges[0], edges[1])
+            ));
+        }
+        edges.iter()
+            .map(|&f| {
+                if f > 0.0 && f < sample_rate / 2.0 {
+                    Ok(f / sample_rate)
+                } else {
+                    Err(WfmError::ParseError(
+                        format!("Frequency {} Hz is outside 0 .. {} Hz (Nyquist)", f, sample_rate / 2.0)
+                    ))
+                }
+            })
+            .collect()
+    }
+}
+
+/// Second-order IIR section `b0 + b1 z⁻¹ + b2 z⁻²` over `1 + a1 z⁻¹ + a2 z⁻²`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Biquad {
+    pub b: [f64; 3],
+    /// Denominator coefficients `a1` and `a2`; `a0` is 1
+    pub a: [f64; 2],
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Design {
+    Iir(Vec<Biquad>),
+    Fir(Vec<f64>),
+}
+
+/// A linear filter for frames of samples
+///
+/// Filters start in the steady state for the first sample, so a DC offset does
+/// not cause a start-up transient.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Filter {
+    design: Design,
+    zero_phase: bool,
+    centred: bool,
+}
+
+impl Filter {
+    /// Butterworth IIR filter of the given order, as a cascade of biquads
+    ///
+    /// Band-pass and band-stop designs have twice the order.
+    pub fn butterworth(order: usize, band: Band, sample_rate: f64) -> Result<Filter> {
+        let poles = (0..order)
+            .map(|k| Complex::from_polar(1.0, PI * (2 * k + order + 1) as f64 / (2 * order) as f64))
+            .collect();
+        Self::from_prototype(order, poles, 1.0, band, sample_rate)
+    }
+
+    /// Chebyshev type I IIR filter with `ripple_db` of pass-band ripple
+    pub fn chebyshev(order: usize, ripple_db: f64, band: Band, sample_rate: f64) -> Result<Filter> {
+        if ripple_db <= 0.0 {
+            return Err(WfmError::ParseError(format!("Ripple must be positive, got {} dB", ripple_db)));
+        }
+        let eps = (10f64.powf(ripple_db / 10.0) - 1.0).sqrt();
+        let mu = (1.0 / eps).asinh() / order as f64;
+        let poles: Vec<Complex> = (0..order)
+            .map(|k| {
+                let theta = PI * (2 * k + 1) as f64 / (2 * order) as f64;
+                Complex::new(-mu.sinh() * theta.sin(), mu.cosh() * theta.cos())
+            })
+            .collect();
+
+        // Unit gain at DC for odd orders, the bottom of the ripple for even ones
+        let mut gain = poles.iter().fold(Complex::new(1.0, 0.0), |acc, &p| acc * -p).re;
+        if order.is_multiple_of(2) {
+            gain /= (1.0 + eps * eps).sqrt();
+        }
+        Self::from_prototype(order, poles, gain, band, sample_rate)
+    }
+
+    /// Second-order notch at `frequency` with quality factor `q`
+    pub fn notch(frequency: f64, q: f64, sample_rate: f64) -> Result<Filter> {
+        let f = Band::LowPass(frequency).normalized(sample_rate)?[0];
+        let w0 = 2.0 * PI * f;
+        let alpha = w0.sin() / (2.0 * q);
+        let a0 = 1.0 + alpha;
+        let section = Biquad {
+            b: [1.0 / a0, -2.0 * w0.cos() / a0, 1.0 / a0],
+            a: [-2.0 * w0.cos() / a0, (1.0 - alpha) / a0],
+        };
+        Ok(Filter::from_sections(vec![section]))
+    }
+
+    /// Linear-phase FIR filter with `taps` coefficients, designed by the
+    /// windowed-sinc method with a Blackman window
+    ///
+    /// `taps` must be odd so high-pass and band-stop designs are possible.
+    /// The kernel is centred, so the output is not delayed.
+    pub fn windowed_sinc(taps: usize, band: Band, sample_rate: f64) -> Result<Filter> {
+        if taps.is_multiple_of(2) {
+            return Err(WfmError::ParseError(format!("FIR filters need an odd number of taps, got {}", taps)));
+        }
+        let edges = band.normalized(sample_rate)?;
+        let lowpass = |fc: f64| -> Vec<f64> {
+            let m = (taps - 1) as f64 / 2.0;
+            let h: Vec<f64> = (0..taps)
+                .map(|n| {
+                    let x = n as f64 - m;
+                    let sinc = if x == 0.0 { 2.0 * fc } else { (2.0 * PI * fc * x).sin() / (PI * x) };
+                    let w = if taps == 1 {
+                        1.0
+                    } else {
+                        let phase = 2.0 * PI * n as f64 / (taps - 1) as f64;
+                        0.42 - 0.5 * phase.cos() + 0.08 * (2.0 * phase).cos()
+                    };
+                    sinc * w
+                })
+                .collect();
+            let sum: f64 = h.iter().sum();
+            h.iter().map(|v| v / sum).collect()
+        };
+        // Spectral inversion turns a low-pass into the complementary high-pass
+        let invert = |h: Vec<f64>| -> Vec<f64> {
+            h.iter().enumerate().map(|(n, v)| if n == taps / 2 { 1.0 - v } else { -v }).collect()
+        };
+
+        let h = match band {
+            Band::LowPass(_) => lowpass(edges[0]),
+            Band::HighPass(_) => invert(lowpass(edges[0])),
+            Band::BandPass(..) | Band::BandStop(..) => {
+                let stop = lowpass(edges[0]).iter()
+                    .zip(invert(lowpass(edges[1])))
+                    .map(|(l, h)| l + h)
+                    .collect();
+                if matches!(band, Band::BandStop(..)) { stop } else { invert(stop) }
+            }
+        };
+        Ok(Filter::from_coefficients(h).centred(true))
+    }
+
+    /// Centred moving average over `length` samples
+    ///
+    /// An even `length` leaves a lag of half a sample.
+    pub fn moving_average(length: usize) -> Result<Filter> {
+        if length == 0 {
+            return Err(WfmError::ParseError("Moving average length must be at least 1".to_string()));
+        }
+        Ok(Filter::from_coefficients(vec![1.0 / length as f64; length]).centred(true))
+    }
+
+    /// Savitzky–Golay smoothing with an odd `window` and polynomial `order`
+    ///
+    /// Each output is the value at the centre of a least-squares polynomial
+    /// fit over the surrounding `window` samples.
+    pub fn savitzky_golay(window: usize, order: usize) -> Result<Filter> {
+        if window.is_multiple_of(2) || order >= window {
+            return Err(WfmError::ParseError(
+                format!("Savitzky-Golay needs an odd window longer than the order, got {} and {}", window, order)
+            ));
+        }
+        let half = (window / 2) as f64;
+        let size = order + 1;
+
+        // Normal equations of the fit: (AᵀA) c = e₀ gives the centre coefficients
+        let mut ata = vec![vec![0.0; size + 1]; size];
+        for (i, row) in ata.iter_mut().enumerate() {
+            for (j, v) in row.iter_mut().take(size).enumerate() {
+                *v = (0..window).map(|n| (n as f64 - half).powi((i + j) as i32)).sum();
+            }
+            row[size] = if i == 0 { 1.0 } else { 0.0 };
+        }
+        let c = solve(ata);
+
+        let h = (0..window)
+            .map(|n| c.iter().enumerate().map(|(j, cj)| cj * (n as f64 - half).powi(j as i32)).sum())
+            .collect();
+        Ok(Filter::from_coefficients(h).centred(true))
+    }
+
+    /// IIR filter from second-order sections
+    pub fn from_sections(sections: Vec<Biquad>) -> Filter {
+        Filter { design: Design::Iir(sections), zero_phase: false, centred: false }
+    }
+
+    /// Causal FIR filter from its impulse response
+    pub fn from_coefficients(coefficients: Vec<f64>) -> Filter {
+        Filter { design: Design::Fir(coefficients), zero_phase: false, centred: false }
+    }
+
+    /// Run the filter forwards and then backwards, cancelling its phase shift
+    ///
+    /// The magnitude response is applied twice. Frames are extended by odd
+    /// reflection at both ends to limit edge effects.
+    pub fn zero_phase(mut self, zero_phase: bool) -> Self {
+        self.zero_phase = zero_phase;
+        self
+    }
+
+    /// Shift the output of an FIR filter back by `(taps - 1) / 2` samples
+    ///
+    /// This cancels the group delay of a linear-phase kernel. Samples past
+    /// the end of the frame are taken as equal to the last one. The FIR
+    /// designs of this module are centred already; IIR filters ignore it.
+    pub fn centred(mut self, centred: bool) -> Self {
+        self.centred = centred;
+        self
+    }
+
+    /// Second-order sections of an IIR filter
+    pub fn sections(&self) -> Option<&[Biquad]> {
+        match &self.design {
+            Design::Iir(sections) => Some(sections),
+            Design::Fir(_) => None,
+        }
+    }
+
+    /// Impulse response of an FIR filter
+    pub fn coefficients(&self) -> Option<&[f64]> {
+        match &self.design {
+            Design::Fir(h) => Some(h),
+            Design::Iir(_) => None,
+        }
+    }
+
+    /// Magnitude response at `frequency` as a fraction of the sample rate
+    ///
+    /// Includes the second pass of a zero-phase filter.
+    pub fn gain(&self, frequency: f64) -> f64 {
+        let z = Complex::from_polar(1.0, -2.0 * PI * frequency);
+        let polynomial = |coefficients: &[f64]| {
+            coefficients.iter()
+                .rev()
+                .fold(Complex::new(0.0, 0.0), |acc, &c| acc * z + Complex::new(c, 0.0))
+        };
+        let gain = match &self.design {
+            Design::Iir(sections) => sections.iter()
+                .map(|s| (polynomial(&s.b) / polynomial(&[1.0, s.a[0], s.a[1]])).abs())
+                .product(),
+            Design::Fir(h) => polynomial(h).abs(),
+        };
+        if self.zero_phase { gain * gain } else { gain }
+    }
+
+    /// Filter one frame of samples
+    pub fn apply(&self, samples: &[f64]) -> Vec<f64> {
+        if samples.is_empty() {
+            return Vec::new();
+        }
+        if !self.zero_phase {
+            return self.run(samples);
+        }
+
+        let order = match &self.design {
+            Design::Iir(sections) => 2 * sections.len() + 1,
+            Design::Fir(h) => h.len(),
+        };
+        let pad = (3 * order).min(samples.len() - 1);
+        let (first, last) = (samples[0], samples[samples.len() - 1]);
+
+        let mut extended = Vec::with_capacity(samples.len() + 2 * pad);
+        extended.extend((1..=pad).rev().map(|i| 2.0 * first - samples[i]));
+        extended.extend_from_slice(samples);
+        extended.extend((1..=pad).map(|i| 2.0 * last - samples[samples.len() - 1 - i]));
+
+        let mut forward = self.run(&extended);
+        forward.reverse();
+        let mut backward = self.run(&forward);
+        backward.reverse();
+        backward[pad..pad + samples.len()].to_vec()
+    }
+
+    /// Single pass starting in the steady state of the first sample
+    fn run(&self, samples: &[f64]) -> Vec<f64> {
+        let mut output = samples.to_vec();
+        match &self.design {
+            Design::Iir(sections) => {
+                for s in sections {
+                    // Transposed direct form II, primed for a constant input
+                    let x0 = output[0];
+                    let dc = s.b.iter().sum::<f64>() / (1.0 + s.a[0] + s.a[1]);
+                    let mut z1 = (dc - s.b[0]) * x0;
+                    let mut z2 = (s.b[2] - s.a[1] * dc) * x0;
+                    for v in output.iter_mut() {
+                        let x = *v;
+                        let y = s.b[0] * x + z1;
+                        z1 = s.b[1] * x - s.a[0] * y + z2;
+                        z2 = s.b[2] * x - s.a[1] * y;
+                        *v = y;
+                    }
+                }
+            }
+            Design::Fir(h) => {
+                let delay = if self.centred { h.len().saturating_sub(1) / 2 } else { 0 };
+                let last = samples.len() - 1;
+                for (n, v) in output.iter_mut().enumerate() {
+                    *v = h.iter()
+                        .enumerate()
+                        .map(|(k, c)| c * samples[(n + delay).saturating_sub(k).min(last)])
+                        .sum();
+                }
+            }
+        }
+        output
+    }
+
+    /// Bilinear transform of an analog low-pass prototype with cutoff 1 rad/s
+    fn from_prototype(order: usize, poles: Vec<Complex>, gain: f64, band: Band, sample_rate: f64) -> Result<Filter> {
+        if order == 0 {
+            return Err(WfmError::ParseError("Filter order must be at least 1".to_string()));
+        }
+
+        // Pre-warp the band edges to analog frequencies at fs = 2
+        let warped: Vec<f64> = band.normalized(sample_rate)?
+            .iter()
+            .map(|f| 4.0 * (PI * f).tan())
+            .collect();
+        let one = Complex::new(1.0, 0.0);
+        let product = |values: &[Complex]| values.iter().fold(one, |acc, &v| acc * v);
+
+        // Frequency transform of the prototype, which has no finite zeros
+        let (zeros, poles, gain) = match band {
+            Band::LowPass(_) => {
+                let wo = warped[0];
+                (vec![], poles.iter().map(|&p| p * wo).collect::<Vec<_>>(), gain * wo.powi(order as i32))
+            }
+            Band::HighPass(_) => {
+                let wo = Complex::new(warped[0], 0.0);
+                let gain = gain * (one / product(&poles.iter().map(|&p| -p).collect::<Vec<_>>())).re;
+                (vec![Complex::new(0.0, 0.0); order], poles.iter().map(|&p| wo / p).collect(), gain)
+            }
+            Band::BandPass(..) | Band::BandStop(..) => {
+                let bw = warped[1] - warped[0];
+                let wo2 = Complex::new(warped[0] * warped[1], 0.0);
+                let stop = matches!(band, Band::BandStop(..));
+                let mut shifted = Vec::with_capacity(2 * order);
+                for &p in &poles {
+                    let q = if stop { Complex::new(bw / 2.0, 0.0) / p } else { p * (bw / 2.0) };
+                    let root = (q * q - wo2).sqrt();
+                    shifted.push(q + root);
+                    shifted.push(q - root);
+                }
+                if stop {
+                    let gain = gain * (one / product(&poles.iter().map(|&p| -p).collect::<Vec<_>>())).re;
+                    let wo = wo2.re.sqrt();
+                    let zeros = (0..2 * order)
+                        .map(|i| Complex::new(0.0, if i % 2 == 0 { wo } else { -wo }))
+                        .collect();
+                    (zeros, shifted, gain)
+                } else {
+                    (vec![Complex::new(0.0, 0.0); order], shifted, gain * bw.powi(order as i32))
+                }
+            }
+        };
+
+        // Bilinear transform with fs = 2; leftover zeros go to Nyquist
+        let fs2 = Complex::new(4.0, 0.0);
+        let gain = gain * (product(&zeros.iter().map(|&z| fs2 - z).collect::<Vec<_>>())
+            / product(&poles.iter().map(|&p| fs2 - p).collect::<Vec<_>>())).re;
+        let mut zeros: Vec<Complex> = zeros.iter().map(|&z| (fs2 + z) / (fs2 - z)).collect();
+        zeros.resize(poles.len(), Complex::new(-1.0, 0.0));
+        let poles: Vec<Complex> = poles.iter().map(|&p| (fs2 + p) / (fs2 - p)).collect();
+
+        let mut sections: Vec<Biquad> = pair_roots(&poles).into_iter()
+            .zip(pair_roots(&zeros))
+            .map(|(a, b)| Biquad { b: [1.0, b[0], b[1]], a })
+            .collect();
+        for v in sections[0].b.iter_mut() {
+            *v *= gain;
+        }
+        Ok(Filter::from_sections(sections))
+    }
+}
+
+/// Group roots into conjugate or real pairs as `[c1, c2]` of `1 + c1 z⁻¹ + c2 z⁻²`
+///
+/// Real roots are paired from both ends of their sorted order; an odd one
+/// out gives a first-order section.
+fn pair_roots(roots: &[Complex]) -> Vec<[f64; 2]> {
+    const TOLERANCE: f64 = 1e-9;
+    let mut real: Vec<f64> = roots.iter().filter(|r| r.im.abs() <= TOLERANCE).map(|r| r.re).collect();
+    real.sort_by(f64::total_cmp);
+
+    let mut pairs: Vec<[f64; 2]> = roots.iter()
+        .filter(|r| r.im > TOLERANCE)
+        .map(|r| [-2.0 * r.re, r.re * r.re + r.im * r.im])
+        .collect();
+    while real.len() > 1 {
+        let (a, b) = (real.remove(0), real.pop().unwrap());
+        pairs.push([-(a + b), a * b]);
+    }
+    if let Some(a) = real.pop() {
+        pairs.push([-a, 0.0]);
+    }
+    pairs
+}
+
+/// Solve an augmented linear system by Gaussian elimination with partial pivoting
+pub(crate) fn solve(mut m: Vec<Vec<f64>>) -> Vec<f64> {
+    let n = m.len();
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&a, &b| m[a][col].abs().total_cmp(&m[b][col].abs())).unwrap();
+        m.swap(col, pivot);
+        let (upper, lower) = m.split_at_mut(col + 1);
+        let pivot_row = &upper[col];
+        for row in lower {
+            let factor = row[col] / pivot_row[col];
+            for (v, p) in row.iter_mut().zip(pivot_row).skip(col) {
+                *v -= factor * p;
+            }
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let sum: f64 = (row + 1..n).map(|k| m[row][k] * x[k]).sum();
+        x[row] = (m[row][n] - sum) / m[row][row];
+    }
+    x
+}
+
+/// Apply `filter` to every frame of `source`
+///
+/// The header and frame timestamps are kept; raw codes are dropped.
+pub(crate) fn filter_frames<S: Waveform + ?Sized>(source: &S, filter: &Filter) -> Result<WfmFile> {
+    let mut scaled_frames = Vec::with_capacity(source.total_samples());
+    for i in 0..source.num_frames() {
+        scaled_frames.extend(filter.apply(&source.try_frame(i)?));
+    }
+    WfmFile::derived(source, scaled_frames, 0..source.num_frames())
+}
+
+/// Minimal complex arithmetic for the filter designs
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    fn from_polar(r: f64, theta: f64) -> Self {
+        Complex::new(r * theta.cos(), r * theta.sin())
+    }
+
+    fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    fn sqrt(self) -> Self {
+        let r = self.abs().sqrt();
+        let theta = self.im.atan2(self.re) / 2.0;
+        Complex::from_polar(r, theta)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, o: Complex) -> Complex {
+        Complex::new(self.re + o.re, self.im + o.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, o: Complex) -> Complex {
+        Complex::new(self.re - o.re, self.im - o.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    fn mul(self, o: Complex) -> Complex {
+        Complex::new(self.re * o.re - self.im * o.im, self.re * o.im + self.im * o.re)
+    }
+}
+
+impl Mul<f64> for Complex {
+    type Output = Complex;
+    fn mul(self, o: f64) -> Complex {
+        Complex::new(self.re * o, self.im * o)
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    fn div(self, o: Complex) -> Complex {
+        let d = o.re * o.re + o.im * o.im;
+        Complex::new((self.re * o.re + self.im * o.im) / d, (self.im * o.re - self.re * o.im) / d)
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_test_wfm;
+    use crate::Analysis;
+
+    fn sine(frequency: f64, samples: usize) -> Vec<f64> {
+        (0..samples).map(|i| (2.0 * PI * frequency * i as f64).sin()).collect()
+    }
+
+    #[test]
+    fn test_iir_designs() {
+        let fs = 1e6;
+        let half_power = 0.5f64.sqrt();
+
+        let lp = Filter::butterworth(4, Band::LowPass(1e5), fs).unwrap();
+        assert_eq!(lp.sections().unwrap().len(), 2);
+        assert!((lp.gain(0.0) - 1.0).abs() < 1e-9);
+        assert!((lp.gain(0.1) - half_power).abs() < 1e-9);
+        assert!(lp.gain(0.4) < 1e-3);
+
+        let hp = Filter::butterworth(3, Band::HighPass(1e5), fs).unwrap();
+        assert!((hp.gain(0.5) - 1.0).abs() < 1e-9);
+        assert!((hp.gain(0.1) - half_power).abs() < 1e-9);
+
+        let bp = Filter::butterworth(2, Band::BandPass(1e5, 2e5), fs).unwrap();
+        assert!((bp.gain(0.1) - half_power).abs() < 1e-9);
+        assert!((bp.gain(0.2) - half_power).abs() < 1e-9);
+        assert!(bp.gain(0.01) < 1e-2 && bp.gain(0.45) < 1e-2);
+
+        let bs = Filter::butterworth(2, Band::BandStop(1e5, 2e5), fs).unwrap();
+        assert!((bs.gain(0.0) - 1.0).abs() < 1e-9);
+        assert!((bs.gain(0.2) - half_power).abs() < 1e-9);
+
+        // Chebyshev pass band ripples between 1 and -1 dB
+        let ripple = 10f64.powf(-1.0 / 20.0);
+        let cheby = Filter::chebyshev(4, 1.0, Band::LowPass(1e5), fs).unwrap();
+        assert!((cheby.gain(0.0) - ripple).abs() < 1e-9);
+        assert!((cheby.gain(0.1) - ripple).abs() < 1e-9);
+        assert!((0..100).all(|i| cheby.gain(i as f64 * 0.001) > ripple - 1e-9));
+
+        let notch = Filter::notch(5e4, 10.0, fs).unwrap();
+        assert!(notch.gain(0.05) < 1e-12);
+        assert!((notch.gain(0.0) - 1.0).abs() < 1e-12);
+
+        // Zero-phase filtering of an in-band sine leaves it in place
+        let input = sine(0.01, 1000);
+        let output = lp.clone().zero_phase(true).apply(&input);
+        let error = |range: std::ops::Range<usize>| input[range.clone()].iter()
+            .zip(&output[range])
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, f64::max);
+        assert!(error(50..950) < 1e-6);
+        assert!(error(0..1000) < 2e-3);
+
+        // A DC offset passes straight through without a start-up transient
+        assert!(lp.apply(&[2.5; 50]).iter().all(|v| (v - 2.5).abs() < 1e-9));
+
+        assert!(Filter::butterworth(4, Band::LowPass(6e5), fs).is_err());
+        assert!(Filter::butterworth(4, Band::BandPass(2e5, 1e5), fs).is_err());
+    }
+
+    #[test]
+    fn test_fir_and_smoothing() {
+        let lp = Filter::windowed_sinc(101, Band::LowPass(1e5), 1e6).unwrap();
+        assert!((lp.gain(0.0) - 1.0).abs() < 1e-9);
+        assert!(lp.gain(0.2) < 1e-3);
+
+        let hp = Filter::windowed_sinc(101, Band::HighPass(1e5), 1e6).unwrap();
+        assert!(hp.gain(0.0) < 1e-9 && (hp.gain(0.4) - 1.0).abs() < 1e-3);
+        let bp = Filter::windowed_sinc(201, Band::BandPass(1e5, 3e5), 1e6).unwrap();
+        assert!((bp.gain(0.2) - 1.0).abs() < 1e-3 && bp.gain(0.0) < 1e-3);
+        let bs = Filter::windowed_sinc(201, Band::BandStop(1e5, 3e5), 1e6).unwrap();
+        assert!(bs.gain(0.2) < 1e-3 && (bs.gain(0.0) - 1.0).abs() < 1e-3);
+        assert!(Filter::windowed_sinc(100, Band::LowPass(1e5), 1e6).is_err());
+
+        // Savitzky-Golay reproduces a quadratic in place away from the edges
+        let sg = Filter::savitzky_golay(7, 2).unwrap();
+        let quadratic: Vec<f64> = (0..20).map(|i| (i * i) as f64 - 3.0 * i as f64).collect();
+        let smoothed = sg.apply(&quadratic);
+        assert!((3..17).all(|n| (smoothed[n] - quadratic[n]).abs() < 1e-9));
+        assert!(Filter::savitzky_golay(6, 2).is_err());
+
+        // Centred kernels leave an in-band sine in place
+        let input = sine(0.01, 1000);
+        let output = lp.apply(&input);
+        assert!((50..950).all(|n| (output[n] - input[n]).abs() < 1e-3));
+
+        let ma = Filter::moving_average(3).unwrap();
+        assert_eq!(ma.apply(&[0.0, 3.0, 9.0, 3.0, 0.0]), [1.0, 4.0, 5.0, 4.0, 1.0]);
+        let causal = Filter::from_coefficients(vec![0.25; 4]);
+        assert_eq!(causal.apply(&[0.0, 4.0, 8.0, 4.0, 0.0]), [0.0, 1.0, 3.0, 4.0, 4.0]);
+        assert_eq!(causal.centred(true).apply(&[0.0, 4.0, 8.0, 4.0, 0.0]), [1.0, 3.0, 4.0, 4.0, 3.0]);
+
+        let input = write_test_wfm(3, 40, |f, i| ((i % 2) as i8) * 10 + f as i8);
+        let wfm = WfmFile::open(input.path()).unwrap();
+        let filtered = wfm.apply_filter(&Filter::moving_average(2).unwrap()).unwrap();
+        assert_eq!(filtered.num_frames(), 3);
+        assert_eq!(filtered.file_content.frame_timestamps, wfm.file_content.frame_timestamps);
+        assert!((filtered.get_frame(2).unwrap()[10] - 0.07).abs() < 1e-12);
+    }
+}
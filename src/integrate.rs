@@ -0,0 +1,307 @@
+// Pulse Integration
+// Gated area, charge and energy per frame with histograms
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::measure::{field_statistics, write_fields_csv};
+use crate::{FrameStats, Result, Waveform, WfmError};
+
+/// Numerical integration rule
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntegrationRule {
+    #[default]
+    Trapezoid,
+    /// Composite Simpson's 1/3 rule, finishing with the 3/8 rule on an odd
+    /// number of intervals
+    Simpson,
+}
+
+impl IntegrationRule {
+    /// Parse a rule name as used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "trapezoid" | "trapz" => Some(IntegrationRule::Trapezoid),
+            "simpson" => Some(IntegrationRule::Simpson),
+            _ => None,
+        }
+    }
+
+    /// Integral of evenly spaced `values` sampled every `dx`
+    pub fn integrate(&self, values: &[f64], dx: f64) -> f64 {
+        let n = values.len();
+        if n < 2 {
+            return 0.0;
+        }
+        let trapezoid = |v: &[f64]| dx * (v.iter().sum::<f64>() - (v[0] + v[v.len() - 1]) / 2.0);
+        if *self == IntegrationRule::Trapezoid || n < 3 {
+            return trapezoid(values);
+        }
+
+        let simpson = |v: &[f64]| {
+            let inner: f64 = v[1..v.len() - 1].iter()
+                .enumerate()
+                .map(|(i, x)| if i % 2 == 0 { 4.0 * x } else { 2.0 * x })
+                .sum();
+            dx / 3.0 * (v[0] + inner + v[v.len() - 1])
+        };
+        if n % 2 == 1 {
+            simpson(values)
+        } else if n == 4 {
+            3.0 * dx / 8.0 * (values[0] + 3.0 * values[1] + 3.0 * values[2] + values[3])
+        } else {
+            let tail = &values[n - 4..];
+            simpson(&values[..n - 3]) + 3.0 * dx / 8.0 * (tail[0] + 3.0 * tail[1] + 3.0 * tail[2] + tail[3])
+        }
+    }
+}
+
+/// Settings used by [`integrate_pulse`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntegrationOptions {
+    pub rule: IntegrationRule,
+    /// Time window on the frame's time axis, or `None` for the whole frame
+    pub gate: Option<Range<f64>>,
+    /// Termination resistance in ohms
+    pub resistance: f64,
+}
+
+impl Default for IntegrationOptions {
+    fn default() -> Self {
+        IntegrationOptions {
+            rule: IntegrationRule::Trapezoid,
+            gate: None,
+            resistance: 50.0,
+        }
+    }
+}
+
+impl IntegrationOptions {
+    /// Trapezoid rule over the whole frame into 50 Ω
+    pub fn new() -> Self {
+        IntegrationOptions::default()
+    }
+
+    /// Set the integration rule
+    pub fn rule(mut self, rule: IntegrationRule) -> Self {
+        self.rule = rule;
+        self
+    }
+
+    /// Integrate only the samples whose time falls inside `gate`, both ends
+    /// included
+    pub fn gate(mut self, gate: Range<f64>) -> Self {
+        self.gate = Some(gate);
+        self
+    }
+
+    /// Set the termination resistance in ohms
+    pub fn resistance(mut self, resistance: f64) -> Self {
+        self.resistance = resistance;
+        self
+    }
+}
+
+/// Integrals of one frame
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PulseIntegral {
+    /// ∫V dt in V·s
+    pub area: f64,
+    /// ∫V dt / R in coulombs
+    pub charge: f64,
+    /// ∫V² / R dt in joules
+    pub energy: f64,
+}
+
+impl PulseIntegral {
+    /// Names of the values, in declaration order
+    pub const FIELD_NAMES: [&'static str; 3] = ["area", "charge", "energy"];
+
+    /// All values as name/value pairs, in declaration order
+    pub fn fields(&self) -> [(&'static str, Option<f64>); 3] {
+        let values = [self.area, self.charge, self.energy];
+        std::array::from_fn(|i| (Self::FIELD_NAMES[i], Some(values[i])))
+    }
+}
+
+/// Integrals of every frame of a capture
+#[derive(Clone, Debug, PartialEq)]
+pub struct IntegrationReport {
+    /// One entry per frame, in frame order
+    pub frames: Vec<PulseIntegral>,
+}
+
+impl IntegrationReport {
+    /// Statistics of area, charge and energy across frames
+    pub fn statistics(&self) -> Vec<(&'static str, FrameStats)> {
+        field_statistics(&PulseIntegral::FIELD_NAMES, self.frames.iter().map(|m| m.fields().to_vec()))
+    }
+
+    /// Histogram of one value per frame, such as `|p| p.charge`
+    pub fn histogram<F: Fn(&PulseIntegral) -> f64>(&self, bins: usize, value: F) -> Histogram {
+        let values: Vec<f64> = self.frames.iter().map(value).collect();
+        Histogram::from_values(&values, bins)
+    }
+
+    /// Write one CSV row of integrals per frame
+    pub fn write_csv<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        let file = File::create(output_file)?;
+        let mut writer = BufWriter::new(file);
+
+        self.write_csv_to(&mut writer)?;
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the per-frame integrals as CSV to any writer
+    pub fn write_csv_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        write_fields_csv(writer, &PulseIntegral::FIELD_NAMES, self.frames.iter().map(|m| m.fields().to_vec()))
+    }
+}
+
+/// Counts of values in equal-width bins between their minimum and maximum
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    /// Bin edges, one more than the number of bins
+    pub edges: Vec<f64>,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    /// Histogram of the finite `values` in `bins` bins
+    ///
+    /// The last bin includes the maximum. If all values are equal the bins
+    /// span half a unit either side of them.
+    pub fn from_values(values: &[f64], bins: usize) -> Histogram {
+        let bins = bins.max(1);
+        let finite = values.iter().copied().filter(|v| v.is_finite());
+        let (mut min, mut max) = finite.clone().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        if min > max {
+            (min, max) = (0.0, 1.0);
+        } else if min == max {
+            (min, max) = (min - 0.5, max + 0.5);
+        }
+
+        let width = (max - min) / bins as f64;
+        let mut counts = vec![0; bins];
+        for v in finite {
+            let bin = (((v - min) / width) as usize).min(bins - 1);
+            counts[bin] += 1;
+        }
+        Histogram {
+            edges: (0..=bins).map(|i| min + i as f64 * width).collect(),
+            counts,
+        }
+    }
+
+    /// Write one CSV row per bin with its edges and count
+    pub fn write_csv<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        let file = File::create(output_file)?;
+        let mut writer = BufWriter::new(file);
+
+        self.write_csv_to(&mut writer)?;
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the histogram as CSV to any writer
+    pub fn write_csv_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(writer, "bin_start,bin_end,count")?;
+        for (edges, count) in self.edges.windows(2).zip(&self.counts) {
+            writeln!(writer, "{},{},{}", edges[0], edges[1], count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Integrate one frame with first sample at `t0`, sampled every `sample_interval`
+pub fn integrate_pulse(samples: &[f64], sample_interval: f64, t0: f64, options: &IntegrationOptions) -> Result<PulseIntegral> {
+    let range = match &options.gate {
+        None => 0..samples.len(),
+        Some(gate) => {
+            let position = |t: f64| (t - t0) / sample_interval;
+            let start = ((position(gate.start) - 1e-9).ceil().max(0.0) as usize).min(samples.len());
+            let end = ((position(gate.end) + 1e-9).floor() + 1.0).max(0.0) as usize;
+            start..end.clamp(start, samples.len())
+        }
+    };
+    if range.len() < 2 {
+        return Err(WfmError::ParseError(
+            format!("Integration gate {:?} holds fewer than two samples", options.gate)
+        ));
+    }
+
+    let gated = &samples[range];
+    let squared: Vec<f64> = gated.iter().map(|v| v * v).collect();
+    let area = options.rule.integrate(gated, sample_interval);
+    Ok(PulseIntegral {
+        area,
+        charge: area / options.resistance,
+        energy: options.rule.integrate(&squared, sample_interval) / options.resistance,
+    })
+}
+
+/// Integrals of every frame, reading each frame once
+pub(crate) fn integrate_frames<S: Waveform + ?Sized>(source: &S, options: &IntegrationOptions) -> Result<IntegrationReport> {
+    let frames = (0..source.num_frames())
+        .map(|i| integrate_pulse(&source.try_frame(i)?, source.sample_interval(), source.t0(), options))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(IntegrationReport { frames })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Analysis, WfmFile};
+
+    #[test]
+    fn test_integration_rules() {
+        // x² on 0..=1: Simpson is exact for odd and even sample counts
+        for n in [3usize, 4, 5, 8, 11] {
+            let dx = 1.0 / (n - 1) as f64;
+            let values: Vec<f64> = (0..n).map(|i| (i as f64 * dx).powi(2)).collect();
+            assert!((IntegrationRule::Simpson.integrate(&values, dx) - 1.0 / 3.0).abs() < 1e-12, "{}", n);
+        }
+        assert_eq!(IntegrationRule::Trapezoid.integrate(&[0.0, 1.0, 4.0], 1.0), 3.0);
+        assert_eq!(IntegrationRule::Simpson.integrate(&[1.0, 3.0], 2.0), 4.0);
+
+        let h = Histogram::from_values(&[1.0, 2.0, 2.5, 4.0, f64::NAN], 3);
+        assert_eq!(h.edges, [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(h.counts, [1, 2, 1]);
+        assert_eq!(Histogram::from_values(&[2.0, 2.0], 2).counts, [0, 2]);
+    }
+
+    #[test]
+    fn test_integrate_frames() {
+        // 2 V pulse from 0 to 10 ns on frames starting at -5 ns, scaled per frame
+        let samples: Vec<f64> = (0..3)
+            .flat_map(|f| (0..30).map(move |i| if (5..=15).contains(&i) { 2.0 * (f + 1) as f64 } else { 0.0 }))
+            .collect();
+        let wfm = WfmFile::from_samples(30, 1e-9, -5e-9, samples).unwrap();
+
+        let options = IntegrationOptions::new().gate(0.0..10e-9).resistance(50.0);
+        let report = wfm.integrate(&options).unwrap();
+        assert_eq!(report.frames.len(), 3);
+        assert!((report.frames[0].area - 20e-9).abs() < 1e-18);
+        assert!((report.frames[0].charge - 0.4e-9).abs() < 1e-18);
+        assert!((report.frames[2].energy - 36.0 * 10e-9 / 50.0).abs() < 1e-15);
+
+        // Without the gate the edges of the pulse add half a sample each
+        let full = wfm.integrate(&IntegrationOptions::new().rule(IntegrationRule::Simpson)).unwrap();
+        assert!(full.frames[0].area > report.frames[0].area);
+
+        let histogram = report.histogram(3, |p| p.charge);
+        assert_eq!(histogram.counts, [1, 1, 1]);
+        assert_eq!(report.statistics()[1].1.count, 3);
+
+        let mut csv = Vec::new();
+        report.write_csv_to(&mut csv).unwrap();
+        assert!(String::from_utf8(csv).unwrap().starts_with("frame,area,charge,energy\n0,"));
+
+        assert!(wfm.integrate(&IntegrationOptions::new().gate(1.0..2.0)).is_err());
+    }
+}
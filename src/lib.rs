@@ -9,8 +9,25 @@
 //! 
 //! - Read WFM v3 files with FastFrame data
 //! - Extract voltage scaling and timing information
-//! - Export data to CSV format
+//! - Export data to CSV, NumPy (`.npy`/`.npz`), MATLAB (`.mat`), raw binary, WAV and NI TDMS formats
 //! - Access individual frames
+//! - Stream frames from disk with [`WfmReader`] for captures larger than memory
+//! - Common [`Waveform`] trait for container-independent frame access
+//! - [`Analysis`] extension trait with the measurements and transforms below
+//! - Per-frame and whole-capture statistics with [`Analysis::stats`]
+//! - IEEE 181-style pulse measurements with [`Analysis::measure_pulses`]
+//! - Frequency, period, duty cycle and jitter with [`Analysis::measure_timing`]
+//! - Mean, standard deviation, envelope and median across frames with [`Analysis::ensemble`]
+//! - Block averaging of consecutive frames with [`Analysis::average_blocks`]
+//! - IIR, FIR and smoothing filters with [`Filter`]
+//! - Per-frame baseline correction with [`Analysis::correct_baseline`]
+//! - Pulse area, charge and energy with [`Analysis::integrate`]
+//! - Peak detection with prominence and width filters via [`Analysis::find_peaks`]
+//! - Optional `serde` support for headers and waveform data
+//! - Optional `ndarray` views of the frames × samples matrix
+//! - Optional `arrow` feature for Parquet and Arrow IPC export
+//! - Optional `xlsx` feature for Excel workbooks with a metadata sheet
+//! - Optional `fft` feature for windowed spectra and Hilbert-transform envelopes
 //! - Proper error handling
 //! 
 //! ## Example
@@ -18,8 +35,7 @@
 //! ```no_run
 //! use wfm_reader::WfmFile;
 //! 
-//! let mut wfm = WfmFile::new();
-//! wfm.load_file("capture.wfm").expect("Failed to load file");
+//! let wfm = WfmFile::open("capture.wfm").expect("Failed to load file");
 //! 
 //! println!("Number of frames: {}", wfm.file_header.num_fastframes);
 //! println!("Samples per frame: {}", wfm.file_header.full_record_length);
@@ -32,7 +48,62 @@
 //!     println!("First sample: {} V", frame[0]);
 //! }
 //! ```
+//! 
+//! Loading can be tuned with [`LoadOptions`]:
+//! 
+//! ```no_run
+//! use wfm_reader::{LoadOptions, WfmFile};
+//! 
+//! let options = LoadOptions::new()
+//!     .frames(10..20)
+//!     .trim_charge(true)
+//!     .recover_truncated(true);
+//! let wfm = WfmFile::open_with("capture.wfm", options).expect("Failed to load file");
+//! ```
 
 mod wfm_tools;
+mod waveform;
+mod analysis;
+mod reader;
+mod export;
+mod csv_import;
+mod stats;
+mod measure;
+mod timing;
+mod ensemble;
+mod average;
+mod filter;
+mod baseline;
+mod integrate;
+mod peaks;
+#[cfg(test)]
+mod test_support;
+#[cfg(feature = "serde")]
+mod serde_samples;
+#[cfg(feature = "ndarray")]
+mod array;
+#[cfg(feature = "fft")]
+mod spectrum;
+#[cfg(feature = "fft")]
+mod analytic;
 
-pub use wfm_tools::{WfmFile, WfmHeader, WfmContent, WfmError, Result};
\ No newline at end of file
+pub use wfm_tools::{WfmFile, WfmHeader, WfmContent, WfmError, Result, LoadOptions, StoragePrecision, HeaderValue};
+pub use wfm_tools::FrameTimestamp;
+pub use waveform::Waveform;
+pub use analysis::Analysis;
+pub use reader::WfmReader;
+pub use stats::{CaptureStats, FrameStats};
+pub use measure::{measure_pulse, top_base, MeasureOptions, PulseMeasurements, PulseReport};
+pub use ensemble::EnsembleStatistic;
+pub use average::PartialBlock;
+pub use filter::{Band, Biquad, Filter};
+pub use baseline::{Baseline, BaselineWindow};
+pub use integrate::{integrate_pulse, Histogram, IntegrationOptions, IntegrationReport, IntegrationRule, PulseIntegral};
+pub use peaks::{find_peaks, Peak, PeakOptions, PeakReport, Polarity};
+pub use timing::{measure_timing, threshold_crossings, Crossing, TimingMeasurements, TimingOptions, TimingReport};
+#[cfg(feature = "fft")]
+pub use spectrum::{spectrum, Spectrum, SpectrumOptions, Window};
+#[cfg(feature = "fft")]
+pub use analytic::{analytic_signal, AnalyticQuantity, AnalyticSignal};
+pub use export::{TableLayout, CsvOptions, CsvColumn, FloatFormat, StreamFormat, SampleType, sidecar_path};
+pub use export::{WavFormat, WavOptions, DEFAULT_WAV_RATE};
\ No newline at end of file
@@ -2,23 +2,135 @@
 // Example command-line application for WFM Reader
 
 use std::env;
+use std::io::{self, Write};
+use std::path::Path;
 use std::process;
-use wfm_reader::WfmFile;
+use wfm_reader::{Analysis, CaptureStats, CsvColumn, CsvOptions, FloatFormat, SampleType, StreamFormat, Waveform, WfmFile, WfmReader};
+use wfm_reader::{Band, Baseline, BaselineWindow, EnsembleStatistic, Filter, FrameStats, IntegrationOptions, IntegrationRule, MeasureOptions, PartialBlock, PeakOptions, PeakReport, Polarity, TimingOptions, WavFormat, WavOptions};
 
 fn print_usage() {
     eprintln!("Usage: wfm_reader <command> <wfm_file> [options]");
     eprintln!();
     eprintln!("Commands:");
-    eprintln!("  info <file>              Display WFM file information");
-    eprintln!("  convert <file> <output>  Convert WFM to CSV");
+    eprintln!("  info <file> [--json]     Display WFM file information");
+    eprintln!("  convert <file> <output>  Convert WFM to another format (see --format)");
     eprintln!("  frames <file> <output>   Export frames as columns to CSV");
     eprintln!("  extract <file> <frame>   Extract a single frame to stdout");
+    eprintln!("  stats <file> [output]    Per-frame statistics, printed or written as CSV");
+    eprintln!("  measure <file> [output]  Pulse measurements: summary printed, per frame as CSV");
+    eprintln!("  timing <file> [output]   Frequency, period, duty cycle and jitter per frame");
+    eprintln!("  integrate <file> [output] Pulse area, charge and energy per frame");
+    eprintln!("  peaks <file> [output]    List the peaks of every frame");
+    eprintln!("  ensemble <file> <output> Per-sample statistic across frames (see --statistic)");
+    eprintln!("  average <file> <output>  Average every --block <n> consecutive frames");
+    eprintln!("  filter <file> <output>   Filter every frame (see filter options)");
+    eprintln!("  baseline <file> <output> Remove the baseline of every frame");
+    eprintln!("  analytic <file> <output> Hilbert envelope, phase or frequency (fft feature)");
+    eprintln!("  spectrum <file> <output> FFT magnitude, phase and PSD as csv, npy or npz (fft feature)");
+    eprintln!();
+    eprintln!("<file> may also be a .csv written by wfm_reader or saved by a Tektronix scope.");
+    eprintln!();
+    eprintln!("Options:");
+    eprintln!("  --format <fmt>           Output format for convert: csv, npy, npz, mat, bin,");
+    eprintln!("                           wav, tdms, parquet, arrow, xlsx");
+    eprintln!("                           (default: from extension)");
+    eprintln!("  --layout <long|wide>     Table layout for parquet/arrow (default: long)");
+    eprintln!("  --dtype <f32|f64|i8>     Sample type for bin output (default: f64)");
+    eprintln!("  --in-memory              Load the whole file before converting; csv, npy and");
+    eprintln!("                           bin are otherwise streamed frame by frame");
+    eprintln!();
+    eprintln!("WAV options (convert --format wav):");
+    eprintln!("  --frame <n>              Export one frame instead of all frames concatenated");
+    eprintln!("  --gap <n>                Samples of silence between frames");
+    eprintln!("  --wav-format <fmt>       pcm16, pcm32 or float (default: pcm16)");
+    eprintln!("  --rate <hz>              Playback rate (default: capture rate)");
+    eprintln!("  --normalize              Scale the peak to full scale");
+    eprintln!();
+    eprintln!("Integrate options:");
+    eprintln!("  --rule <name>            trapezoid or simpson (default: trapezoid)");
+    eprintln!("  --gate <t1,t2>           Time window in seconds on the frame time axis");
+    eprintln!("  --resistance <ohms>      Termination resistance (default: 50)");
+    eprintln!("  --histogram <file>       Write a histogram of --quantity as CSV");
+    eprintln!("  --quantity <name>        area, charge or energy (default: charge)");
+    eprintln!("  --bins <n>               Histogram bins (default: 50)");
+    eprintln!();
+    eprintln!("Peak options (times in seconds):");
+    eprintln!("  --polarity <name>        positive, negative or both (default: positive)");
+    eprintln!("  --height <V>             Minimum peak value");
+    eprintln!("  --prominence <V>         Minimum height above the surrounding bases");
+    eprintln!("  --width <t>              Minimum width at half prominence");
+    eprintln!("  --separation <t>         Minimum time between peaks");
+    eprintln!();
+    eprintln!("Ensemble options:");
+    eprintln!("  --statistic <name>       mean, std, min, max, envelope or median (default: mean)");
+    eprintln!("  --frames <list>          Frames to include, e.g. 0,3,10..20 (default: all)");
+    eprintln!();
+    eprintln!("Average options:");
+    eprintln!("  --block <n>              Frames per averaged block (required)");
+    eprintln!("  --partial <mode>         Trailing frames: discard, keep or error (default: discard)");
+    eprintln!();
+    eprintln!("Filter options (frequencies in Hz):");
+    eprintln!("  --lowpass <f> | --highpass <f> | --bandpass <f1,f2> | --bandstop <f1,f2>");
+    eprintln!("  --design <name>          butterworth, chebyshev or fir (default: butterworth)");
+    eprintln!("  --order <n>              IIR order or FIR taps (default: 4, or 101 taps)");
+    eprintln!("  --ripple <dB>            Chebyshev pass-band ripple (default: 1)");
+    eprintln!("  --notch <f> [--q <q>]    Second-order notch (default q: 30)");
+    eprintln!("  --moving-average <n>     Moving average over n samples");
+    eprintln!("  --savgol <window,order>  Savitzky-Golay smoothing");
+    eprintln!("  --zero-phase             Filter forwards and backwards");
+    eprintln!();
+    eprintln!("Baseline options (windows are start..end in samples, or in seconds from the");
+    eprintln!("first sample with an s suffix, e.g. 0..2e-6s):");
+    eprintln!("  --mode <name>            mean, median, poly or linear (default: mean)");
+    eprintln!("  --window <range>         Reference window (default: pre-trigger samples)");
+    eprintln!("  --window2 <range>        Second window for linear mode");
+    eprintln!("  --degree <n>             Polynomial degree for poly mode (default: 1)");
+    eprintln!();
+    eprintln!("Spectrum options:");
+    eprintln!("  --window <name>          rectangular, hann, blackman-harris or flat-top (default: hann)");
+    eprintln!("  --fft-length <n>         Zero-pad every frame to n samples");
+    eprintln!("  --db                     Magnitude in dBV and PSD in dB re 1 V^2/Hz");
+    eprintln!("  --average                Average the spectra of all frames");
+    eprintln!("  --format <fmt>           csv, npy or npz (default: from the output extension)");
+    eprintln!();
+    eprintln!("Analytic options:");
+    eprintln!("  --quantity <name>        envelope, phase (rad) or frequency (Hz) (default: envelope)");
+    eprintln!();
+    eprintln!("Measure options (levels in percent of the amplitude):");
+    eprintln!("  --low <pct> --high <pct> Rise/fall reference levels (default: 10, 90)");
+    eprintln!("  --mid <pct>              Width and settling reference level (default: 50)");
+    eprintln!("  --band <pct>             Settling tolerance (default: 2)");
+    eprintln!();
+    eprintln!("Timing options:");
+    eprintln!("  --threshold <V>          Threshold level (default: halfway between top and base)");
+    eprintln!("  --hysteresis <pct>       Hysteresis band in percent of the amplitude (default: 10)");
+    eprintln!();
+    eprintln!("CSV options (convert, frames, extract):");
+    eprintln!("  --columns <list>         Comma-separated: frame,sample,time,voltage,raw");
+    eprintln!("  --delimiter <char>       Field separator, or 'tab' (default: ,)");
+    eprintln!("  --decimal-comma          Write 0,5 instead of 0.5, separating fields with ;");
+    eprintln!("  --precision <n>          Fixed-point with n decimal places");
+    eprintln!("  --scientific <n>         Scientific notation with n decimal places");
+    eprintln!("  --units                  Add units to column names");
+    eprintln!("  --metadata               Start the file with a # comment header");
     eprintln!();
     eprintln!("Examples:");
     eprintln!("  wfm_reader info capture.wfm");
     eprintln!("  wfm_reader convert capture.wfm output.csv");
-    eprintln!("  wfm_reader frames capture.wfm frames.csv");
+    eprintln!("  wfm_reader convert capture.wfm output.npz --format npz");
+    eprintln!("  wfm_reader frames capture.wfm frames.csv --columns time,voltage --units");
     eprintln!("  wfm_reader extract capture.wfm 0 > frame0.txt");
+    eprintln!("  wfm_reader stats capture.wfm stats.csv");
+    eprintln!("  wfm_reader measure capture.wfm --low 20 --high 80");
+    eprintln!("  wfm_reader timing capture.wfm timing.csv --threshold 1.4");
+    eprintln!("  wfm_reader integrate capture.wfm charge.csv --gate 0,50e-9 --histogram hist.csv");
+    eprintln!("  wfm_reader peaks capture.wfm --prominence 0.2 --separation 1e-6");
+    eprintln!("  wfm_reader ensemble capture.wfm median.npy --statistic median --frames 0..100");
+    eprintln!("  wfm_reader average capture.wfm averaged.tdms --block 64");
+    eprintln!("  wfm_reader filter capture.wfm filtered.npz --bandpass 1e6,5e6 --order 4 --zero-phase");
+    eprintln!("  wfm_reader baseline capture.wfm corrected.mat --mode median --window 0..500");
+    eprintln!("  wfm_reader analytic capture.wfm envelope.npz --quantity envelope");
+    eprintln!("  wfm_reader spectrum capture.wfm spectrum.csv --window flat-top --db --average");
 }
 
 fn main() {
@@ -32,16 +144,14 @@ fn main() {
     let command = &args[1];
     let input_file = &args[2];
     
-    // Load the WFM file
-    let mut wfm = WfmFile::new();
-    if let Err(e) = wfm.load_file(input_file) {
-        eprintln!("Error loading WFM file '{}': {}", input_file, e);
-        process::exit(1);
-    }
-    
     match command.as_str() {
         "info" => {
-            print_file_info(&wfm);
+            let wfm = load_file(input_file);
+            if args[3..].iter().any(|a| a == "--json") {
+                print_header_json(&wfm);
+            } else {
+                print_file_info(&wfm);
+            }
         }
         
         "convert" => {
@@ -52,13 +162,51 @@ fn main() {
             }
             
             let output_file = &args[3];
-            if let Err(e) = wfm.write_csv(output_file) {
-                eprintln!("Error writing CSV file '{}': {}", output_file, e);
+            let format = option_value(&args[4..], "--format")
+                .map(str::to_string)
+                .unwrap_or_else(|| format_from_extension(output_file));
+            
+            // Stream straight from disk unless the format needs all frames at once
+            let streamed = match format.as_str() {
+                "csv" => Some(StreamFormat::Csv(csv_options(&args[4..], CsvOptions::new()))),
+                "npy" => Some(StreamFormat::Npy),
+                "bin" => Some(StreamFormat::Binary(sample_type(&args[4..]))),
+                _ => None,
+            };
+            let in_memory = args[4..].iter().any(|a| a == "--in-memory") || is_csv(input_file);
+            
+            if let (Some(stream_format), false) = (streamed, in_memory) {
+                let reader = match WfmReader::open(input_file) {
+                    Ok(reader) => reader,
+                    Err(e) => {
+                        eprintln!("Error opening WFM file '{}': {}", input_file, e);
+                        process::exit(1);
+                    }
+                };
+                
+                match reader.convert(output_file, &stream_format) {
+                    Ok(samples) => {
+                        println!("Successfully converted {} to {}", input_file, output_file);
+                        println!("Total samples written: {}", samples);
+                    }
+                    Err(e) => {
+                        eprintln!("Error writing {} file '{}': {}", format.to_uppercase(), output_file, e);
+                        process::exit(1);
+                    }
+                }
+                return;
+            }
+            
+            let wfm = load_file(input_file);
+            let result = write_output(&wfm, output_file, &format, &args[4..]);
+            
+            if let Err(e) = result {
+                eprintln!("Error writing {} file '{}': {}", format.to_uppercase(), output_file, e);
                 process::exit(1);
             }
             
             println!("Successfully converted {} to {}", input_file, output_file);
-            println!("Total samples written: {}", wfm.file_content.scaled_frames.len());
+            println!("Total samples written: {}", wfm.total_samples());
         }
         
         "frames" => {
@@ -68,8 +216,10 @@ fn main() {
                 process::exit(1);
             }
             
+            let wfm = load_file(input_file);
             let output_file = &args[3];
-            if let Err(e) = wfm.write_csv_by_frame(output_file) {
+            let options = csv_options(&args[4..], CsvOptions::wide());
+            if let Err(e) = wfm.write_csv_with(output_file, &options) {
                 eprintln!("Error writing frame CSV file '{}': {}", output_file, e);
                 process::exit(1);
             }
@@ -93,22 +243,471 @@ fn main() {
                 }
             };
             
-            match wfm.get_frame(frame_num) {
-                Some(frame_data) => {
-                    let times = wfm.get_time_values();
-                    println!("# Frame {} from {}", frame_num, input_file);
-                    println!("# Time (s), Voltage (V)");
-                    
-                    for (i, &voltage) in frame_data.iter().enumerate() {
-                        println!("{:.12e}, {:.6e}", times[i], voltage);
+            let wfm = load_file(input_file);
+            let Some(frame_data) = wfm.get_frame(frame_num) else {
+                eprintln!("Error: Frame {} not found (file has {} frames)", 
+                         frame_num, wfm.file_header.num_fastframes);
+                process::exit(1);
+            };
+            
+            if !has_csv_options(&args[4..]) {
+                let times = wfm.get_time_values();
+                println!("# Frame {} from {}", frame_num, input_file);
+                println!("# Time (s), Voltage (V)");
+                
+                for (i, &voltage) in frame_data.iter().enumerate() {
+                    println!("{:.12e}, {:.6e}", times[i], voltage);
+                }
+            } else {
+                let base = CsvOptions::new()
+                    .columns(&[CsvColumn::Time, CsvColumn::Voltage])
+                    .float_format(FloatFormat::Scientific(12))
+                    .units(true);
+                let options = csv_options(&args[4..], base).frames(frame_num..frame_num + 1);
+                
+                let stdout = io::stdout();
+                let mut out = io::BufWriter::new(stdout.lock());
+                let result = writeln!(out, "# Frame {} from {}", frame_num, input_file)
+                    .map_err(Into::into)
+                    .and_then(|_| wfm.write_csv_to(&mut out, &options))
+                    .and_then(|_| out.flush().map_err(Into::into));
+                if let Err(e) = result {
+                    eprintln!("Error writing frame {}: {}", frame_num, e);
+                    process::exit(1);
+                }
+            }
+        }
+        
+        "stats" => {
+            let stats = match open_source(input_file).stats() {
+                Ok(stats) => stats,
+                Err(e) => {
+                    eprintln!("Error computing statistics for '{}': {}", input_file, e);
+                    process::exit(1);
+                }
+            };
+            
+            match output_arg(&args) {
+                Some(output_file) => {
+                    if let Err(e) = stats.write_csv(output_file) {
+                        eprintln!("Error writing statistics file '{}': {}", output_file, e);
+                        process::exit(1);
+                    }
+                    println!("Successfully wrote statistics of {} frames to {}", stats.frames.len(), output_file);
+                }
+                None => print_stats_table(&stats),
+            }
+        }
+        
+        "measure" => {
+            let mut options = MeasureOptions::new();
+            let percent = |name: &str| parse_option::<f64>(&args[3..], name).map(|v| v / 100.0);
+            options.low_ref = percent("--low").unwrap_or(options.low_ref);
+            options.mid_ref = percent("--mid").unwrap_or(options.mid_ref);
+            options.high_ref = percent("--high").unwrap_or(options.high_ref);
+            options.settling_band = percent("--band").unwrap_or(options.settling_band);
+            
+            let report = match open_source(input_file).measure_pulses(&options) {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("Error measuring '{}': {}", input_file, e);
+                    process::exit(1);
+                }
+            };
+            
+            match output_arg(&args) {
+                Some(output_file) => {
+                    if let Err(e) = report.write_csv(output_file) {
+                        eprintln!("Error writing measurement file '{}': {}", output_file, e);
+                        process::exit(1);
+                    }
+                    println!("Successfully wrote measurements of {} frames to {}", report.frames.len(), output_file);
+                }
+                None => print_measurement_table(report.statistics(), |name| match name {
+                    "top" | "base" | "amplitude" => "V",
+                    "overshoot" | "undershoot" => "%",
+                    _ => "s",
+                }),
+            }
+        }
+        
+        "timing" => {
+            let mut options = TimingOptions::new();
+            if let Some(threshold) = parse_option::<f64>(&args[3..], "--threshold") {
+                options = options.threshold(threshold);
+            }
+            if let Some(hysteresis) = parse_option::<f64>(&args[3..], "--hysteresis") {
+                options = options.hysteresis(hysteresis / 100.0);
+            }
+            
+            let report = match open_source(input_file).measure_timing(&options) {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("Error measuring '{}': {}", input_file, e);
+                    process::exit(1);
+                }
+            };
+            
+            match output_arg(&args) {
+                Some(output_file) => {
+                    if let Err(e) = report.write_csv(output_file) {
+                        eprintln!("Error writing timing file '{}': {}", output_file, e);
+                        process::exit(1);
+                    }
+                    println!("Successfully wrote timing of {} frames to {}", report.frames.len(), output_file);
+                }
+                None => print_measurement_table(report.statistics(), |name| match name {
+                    "cycles" => "",
+                    "frequency" => "Hz",
+                    "duty_cycle" => "%",
+                    _ => "s",
+                }),
+            }
+        }
+        
+        "integrate" => {
+            let mut options = IntegrationOptions::new();
+            if let Some(name) = option_value(&args[3..], "--rule") {
+                options = options.rule(IntegrationRule::from_name(name).unwrap_or_else(|| {
+                    eprintln!("Error: Unknown integration rule '{}'", name);
+                    process::exit(1);
+                }));
+            }
+            if let Some(gate) = option_value(&args[3..], "--gate") {
+                let bounds: Vec<f64> = gate.split(',').filter_map(|t| t.trim().parse().ok()).collect();
+                if bounds.len() != 2 {
+                    eprintln!("Error: --gate needs two comma-separated times");
+                    process::exit(1);
+                }
+                options = options.gate(bounds[0]..bounds[1]);
+            }
+            if let Some(resistance) = parse_option(&args[3..], "--resistance") {
+                options = options.resistance(resistance);
+            }
+            
+            let report = match open_source(input_file).integrate(&options) {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("Error integrating '{}': {}", input_file, e);
+                    process::exit(1);
+                }
+            };
+            
+            if let Some(histogram_file) = option_value(&args[3..], "--histogram") {
+                let bins = parse_option(&args[3..], "--bins").unwrap_or(50);
+                let histogram = match option_value(&args[3..], "--quantity").unwrap_or("charge") {
+                    "area" => report.histogram(bins, |p| p.area),
+                    "charge" => report.histogram(bins, |p| p.charge),
+                    "energy" => report.histogram(bins, |p| p.energy),
+                    other => {
+                        eprintln!("Error: Unknown quantity '{}'", other);
+                        process::exit(1);
+                    }
+                };
+                if let Err(e) = histogram.write_csv(histogram_file) {
+                    eprintln!("Error writing histogram file '{}': {}", histogram_file, e);
+                    process::exit(1);
+                }
+                println!("Successfully wrote histogram of {} frames to {}", report.frames.len(), histogram_file);
+            }
+            
+            match output_arg(&args) {
+                Some(output_file) => {
+                    if let Err(e) = report.write_csv(output_file) {
+                        eprintln!("Error writing integration file '{}': {}", output_file, e);
+                        process::exit(1);
+                    }
+                    println!("Successfully wrote integrals of {} frames to {}", report.frames.len(), output_file);
+                }
+                None => print_measurement_table(report.statistics(), |name| match name {
+                    "area" => "V s",
+                    "charge" => "C",
+                    _ => "J",
+                }),
+            }
+        }
+        
+        "peaks" => {
+            let mut options = PeakOptions::new();
+            if let Some(name) = option_value(&args[3..], "--polarity") {
+                options = options.polarity(Polarity::from_name(name).unwrap_or_else(|| {
+                    eprintln!("Error: Unknown polarity '{}'", name);
+                    process::exit(1);
+                }));
+            }
+            options.height = parse_option(&args[3..], "--height");
+            options.prominence = parse_option(&args[3..], "--prominence");
+            options.width = parse_option(&args[3..], "--width");
+            options.separation = parse_option(&args[3..], "--separation");
+            
+            let report = match open_source(input_file).find_peaks(&options) {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("Error finding peaks in '{}': {}", input_file, e);
+                    process::exit(1);
+                }
+            };
+            
+            match output_arg(&args) {
+                Some(output_file) => {
+                    if let Err(e) = report.write_csv(output_file) {
+                        eprintln!("Error writing peak file '{}': {}", output_file, e);
+                        process::exit(1);
+                    }
+                    println!("Successfully wrote {} peaks of {} frames to {}", report.total(), report.frames.len(), output_file);
+                }
+                None => print_peak_table(&report),
+            }
+        }
+        
+        "ensemble" => {
+            if args.len() < 4 {
+                eprintln!("Error: Missing output file argument");
+                print_usage();
+                process::exit(1);
+            }
+            
+            let output_file = &args[3];
+            let format = option_value(&args[4..], "--format")
+                .map(str::to_string)
+                .unwrap_or_else(|| format_from_extension(output_file));
+            let statistic = match option_value(&args[4..], "--statistic") {
+                None => EnsembleStatistic::Mean,
+                Some(name) => EnsembleStatistic::from_name(name).unwrap_or_else(|| {
+                    eprintln!("Error: Unknown ensemble statistic '{}'", name);
+                    process::exit(1);
+                }),
+            };
+            
+            let source = open_source(input_file);
+            let frames = frame_list(&args[4..], source.num_frames());
+            let ensemble = match source.ensemble_frames(statistic, &frames) {
+                Ok(ensemble) => ensemble,
+                Err(e) => {
+                    eprintln!("Error computing ensemble of '{}': {}", input_file, e);
+                    process::exit(1);
+                }
+            };
+            
+            if let Err(e) = write_output(&ensemble, output_file, &format, &args[4..]) {
+                eprintln!("Error writing {} file '{}': {}", format.to_uppercase(), output_file, e);
+                process::exit(1);
+            }
+            println!("Successfully wrote ensemble of {} frames to {}", frames.len(), output_file);
+        }
+        
+        "average" => {
+            if args.len() < 4 {
+                eprintln!("Error: Missing output file argument");
+                print_usage();
+                process::exit(1);
+            }
+            
+            let output_file = &args[3];
+            let format = option_value(&args[4..], "--format")
+                .map(str::to_string)
+                .unwrap_or_else(|| format_from_extension(output_file));
+            let Some(block) = parse_option::<u32>(&args[4..], "--block") else {
+                eprintln!("Error: Missing --block <n> argument");
+                process::exit(1);
+            };
+            let partial = match option_value(&args[4..], "--partial") {
+                None => PartialBlock::Discard,
+                Some(name) => PartialBlock::from_name(name).unwrap_or_else(|| {
+                    eprintln!("Error: Unknown partial block mode '{}'", name);
+                    process::exit(1);
+                }),
+            };
+            
+            let source = open_source(input_file);
+            let averaged = match source.average_blocks_with(block, partial) {
+                Ok(averaged) => averaged,
+                Err(e) => {
+                    eprintln!("Error averaging '{}': {}", input_file, e);
+                    process::exit(1);
+                }
+            };
+            
+            if let Err(e) = write_output(&averaged, output_file, &format, &args[4..]) {
+                eprintln!("Error writing {} file '{}': {}", format.to_uppercase(), output_file, e);
+                process::exit(1);
+            }
+            println!("Successfully averaged {} frames into {} blocks in {}",
+                     source.num_frames(), averaged.num_frames(), output_file);
+        }
+        
+        "filter" => {
+            if args.len() < 4 {
+                eprintln!("Error: Missing output file argument");
+                print_usage();
+                process::exit(1);
+            }
+            
+            let output_file = &args[3];
+            let format = option_value(&args[4..], "--format")
+                .map(str::to_string)
+                .unwrap_or_else(|| format_from_extension(output_file));
+            
+            let source = open_source(input_file);
+            let filter = match build_filter(&args[4..], 1.0 / source.sample_interval()) {
+                Ok(filter) => filter.zero_phase(args[4..].iter().any(|a| a == "--zero-phase")),
+                Err(e) => {
+                    eprintln!("Error designing filter: {}", e);
+                    process::exit(1);
+                }
+            };
+            
+            let filtered = match source.apply_filter(&filter) {
+                Ok(filtered) => filtered,
+                Err(e) => {
+                    eprintln!("Error filtering '{}': {}", input_file, e);
+                    process::exit(1);
+                }
+            };
+            if let Err(e) = write_output(&filtered, output_file, &format, &args[4..]) {
+                eprintln!("Error writing {} file '{}': {}", format.to_uppercase(), output_file, e);
+                process::exit(1);
+            }
+            println!("Successfully filtered {} frames to {}", filtered.num_frames(), output_file);
+        }
+        
+        "baseline" => {
+            if args.len() < 4 {
+                eprintln!("Error: Missing output file argument");
+                print_usage();
+                process::exit(1);
+            }
+            
+            let output_file = &args[3];
+            let format = option_value(&args[4..], "--format")
+                .map(str::to_string)
+                .unwrap_or_else(|| format_from_extension(output_file));
+            let window = |name: &str| option_value(&args[4..], name).map(baseline_window);
+            let first = window("--window").unwrap_or(BaselineWindow::PreTrigger);
+            let baseline = match option_value(&args[4..], "--mode").unwrap_or("mean") {
+                "mean" => Baseline::Mean(first),
+                "median" => Baseline::Median(first),
+                "poly" => Baseline::Polynomial(parse_option(&args[4..], "--degree").unwrap_or(1), first),
+                "linear" => match window("--window2") {
+                    Some(second) => Baseline::Linear(first, second),
+                    None => {
+                        eprintln!("Error: Linear baseline needs --window2 <range>");
+                        process::exit(1);
                     }
+                },
+                other => {
+                    eprintln!("Error: Unknown baseline mode '{}'", other);
+                    process::exit(1);
+                }
+            };
+            
+            let corrected = match open_source(input_file).correct_baseline(&baseline) {
+                Ok(corrected) => corrected,
+                Err(e) => {
+                    eprintln!("Error correcting baseline of '{}': {}", input_file, e);
+                    process::exit(1);
                 }
-                None => {
-                    eprintln!("Error: Frame {} not found (file has {} frames)", 
-                             frame_num, wfm.file_header.num_fastframes);
+            };
+            if let Err(e) = write_output(&corrected, output_file, &format, &args[4..]) {
+                eprintln!("Error writing {} file '{}': {}", format.to_uppercase(), output_file, e);
+                process::exit(1);
+            }
+            println!("Successfully corrected {} frames to {}", corrected.num_frames(), output_file);
+        }
+        
+        #[cfg(feature = "fft")]
+        "spectrum" => {
+            if args.len() < 4 {
+                eprintln!("Error: Missing output file argument");
+                print_usage();
+                process::exit(1);
+            }
+            
+            let output_file = &args[3];
+            // Unlike waveform outputs, unknown extensions are not written as CSV
+            let format = option_value(&args[4..], "--format")
+                .map(str::to_string)
+                .or_else(|| Path::new(output_file).extension().map(|e| e.to_string_lossy().to_lowercase()))
+                .unwrap_or_default();
+            if !matches!(format.as_str(), "csv" | "npy" | "npz") {
+                eprintln!("Error: Unsupported spectrum output format '{}' (use csv, npy or npz)", format);
+                process::exit(1);
+            }
+            let mut options = wfm_reader::SpectrumOptions::new()
+                .decibels(args[4..].iter().any(|a| a == "--db"))
+                .average(args[4..].iter().any(|a| a == "--average"));
+            if let Some(name) = option_value(&args[4..], "--window") {
+                options = options.window(wfm_reader::Window::from_name(name).unwrap_or_else(|| {
+                    eprintln!("Error: Unknown window '{}'", name);
+                    process::exit(1);
+                }));
+            }
+            if let Some(length) = parse_option::<usize>(&args[4..], "--fft-length") {
+                options = options.fft_length(length);
+            }
+            
+            let spectrum = match open_source(input_file).spectrum(&options) {
+                Ok(spectrum) => spectrum,
+                Err(e) => {
+                    eprintln!("Error computing spectrum of '{}': {}", input_file, e);
                     process::exit(1);
                 }
+            };
+            
+            let result = match format.as_str() {
+                "npy" => spectrum.write_npy(output_file),
+                "npz" => spectrum.write_npz(output_file),
+                _ => spectrum.write_csv(output_file),
+            };
+            if let Err(e) = result {
+                eprintln!("Error writing spectrum file '{}': {}", output_file, e);
+                process::exit(1);
             }
+            println!("Successfully wrote {} spectra of {} bins to {}", spectrum.num_frames(), spectrum.bins, output_file);
+        }
+        
+        #[cfg(feature = "fft")]
+        "analytic" => {
+            if args.len() < 4 {
+                eprintln!("Error: Missing output file argument");
+                print_usage();
+                process::exit(1);
+            }
+            
+            let output_file = &args[3];
+            let format = option_value(&args[4..], "--format")
+                .map(str::to_string)
+                .unwrap_or_else(|| format_from_extension(output_file));
+            let name = option_value(&args[4..], "--quantity").unwrap_or("envelope");
+            let quantity = wfm_reader::AnalyticQuantity::from_name(name).unwrap_or_else(|| {
+                eprintln!("Error: Unknown quantity '{}'", name);
+                process::exit(1);
+            });
+            
+            let result = match open_source(input_file).analytic(quantity) {
+                Ok(result) => result,
+                Err(e) => {
+                    eprintln!("Error computing the analytic signal of '{}': {}", input_file, e);
+                    process::exit(1);
+                }
+            };
+            if let Err(e) = write_output(&result, output_file, &format, &args[4..]) {
+                eprintln!("Error writing {} file '{}': {}", format.to_uppercase(), output_file, e);
+                process::exit(1);
+            }
+            println!("Successfully wrote the {} of {} frames to {}", name, result.num_frames(), output_file);
+        }
+        
+        #[cfg(not(feature = "fft"))]
+        "spectrum" => {
+            eprintln!("Error: spectrum requires wfm_reader to be built with the 'fft' feature");
+            process::exit(1);
+        }
+        
+        #[cfg(not(feature = "fft"))]
+        "analytic" => {
+            eprintln!("Error: analytic requires wfm_reader to be built with the 'fft' feature");
+            process::exit(1);
         }
         
         _ => {
@@ -119,6 +718,328 @@ fn main() {
     }
 }
 
+/// Load a WFM or CSV file into memory, exiting with a message on failure
+fn load_file(input_file: &str) -> WfmFile {
+    let result = if is_csv(input_file) {
+        WfmFile::from_csv(input_file)
+    } else {
+        WfmFile::open(input_file)
+    };
+    match result {
+        Ok(wfm) => wfm,
+        Err(e) => {
+            eprintln!("Error loading '{}': {}", input_file, e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Open a file for frame-by-frame processing
+///
+/// WFM files are read from disk one frame at a time; CSV files are loaded.
+fn open_source(input_file: &str) -> Box<dyn Waveform> {
+    if is_csv(input_file) {
+        return Box::new(load_file(input_file));
+    }
+    match WfmReader::open(input_file) {
+        Ok(reader) => Box::new(reader),
+        Err(e) => {
+            eprintln!("Error opening WFM file '{}': {}", input_file, e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Optional output file following the input file of a command
+fn output_arg(args: &[String]) -> Option<&str> {
+    args.get(3).map(String::as_str).filter(|a| !a.starts_with("--"))
+}
+
+/// Whether an input file is CSV rather than WFM, judged by its extension
+fn is_csv(input_file: &str) -> bool {
+    Path::new(input_file)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"))
+}
+
+/// Value following a `--name value` option, if present
+fn option_value<'a>(args: &'a [String], name: &str) -> Option<&'a str> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .map(String::as_str)
+}
+
+/// CSV options from the command line, applied on top of `base`
+fn csv_options(args: &[String], mut base: CsvOptions) -> CsvOptions {
+    if let Some(list) = option_value(args, "--columns") {
+        let columns: Vec<CsvColumn> = list.split(',')
+            .map(|name| CsvColumn::from_name(name.trim()).unwrap_or_else(|| {
+                eprintln!("Error: Unknown CSV column '{}'", name);
+                process::exit(1);
+            }))
+            .collect();
+        base = base.columns(&columns);
+    }
+    
+    if let Some(delimiter) = option_value(args, "--delimiter") {
+        let delimiter = match delimiter {
+            "tab" | "\\t" => '\t',
+            d if d.chars().count() == 1 => d.chars().next().unwrap(),
+            d => {
+                eprintln!("Error: Delimiter must be a single character, got '{}'", d);
+                process::exit(1);
+            }
+        };
+        base = base.delimiter(delimiter);
+    }
+    
+    for (name, make) in [
+        ("--precision", FloatFormat::Fixed as fn(usize) -> FloatFormat),
+        ("--scientific", FloatFormat::Scientific),
+    ] {
+        if let Some(value) = option_value(args, name) {
+            match value.parse() {
+                Ok(p) => base = base.float_format(make(p)),
+                Err(_) => {
+                    eprintln!("Error: Invalid value '{}' for {}", value, name);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+    
+    let flag = |name: &str| args.iter().any(|a| a == name);
+    if flag("--decimal-comma") {
+        base = base.decimal_comma(true);
+    }
+    if flag("--units") {
+        base = base.units(true);
+    }
+    if flag("--metadata") {
+        base = base.metadata_header(true);
+    }
+    
+    base
+}
+
+/// Whether any CSV option is given, as opposed to the plain defaults
+fn has_csv_options(args: &[String]) -> bool {
+    const NAMES: [&str; 7] = ["--columns", "--delimiter", "--decimal-comma", "--precision",
+                              "--scientific", "--units", "--metadata"];
+    args.iter().any(|a| NAMES.contains(&a.as_str()))
+}
+
+/// Sample type selected with `--dtype`, defaulting to f64
+fn sample_type(args: &[String]) -> SampleType {
+    match option_value(args, "--dtype") {
+        None => SampleType::F64,
+        Some(name) => SampleType::from_name(name).unwrap_or_else(|| {
+            eprintln!("Error: Unknown sample type '{}'", name);
+            process::exit(1);
+        }),
+    }
+}
+
+/// Write an in-memory waveform in the given output format, exiting on unknown formats
+fn write_output(wfm: &WfmFile, output_file: &str, format: &str, args: &[String]) -> wfm_reader::Result<()> {
+    match format {
+        "csv" => wfm.write_csv_with(output_file, &csv_options(args, CsvOptions::new())),
+        "npy" => wfm.write_npy(output_file),
+        "npz" => wfm.write_npz(output_file),
+        "mat" => wfm.write_mat(output_file),
+        "bin" => wfm.write_raw_binary(output_file, sample_type(args)),
+        "wav" => wfm.write_wav(output_file, &wav_options(args)),
+        "tdms" => wfm.write_tdms(output_file),
+        #[cfg(feature = "arrow")]
+        "parquet" => wfm.write_parquet(output_file, table_layout(args)),
+        #[cfg(feature = "arrow")]
+        "arrow" => wfm.write_arrow_ipc(output_file, table_layout(args)),
+        #[cfg(not(feature = "arrow"))]
+        "parquet" | "arrow" => {
+            eprintln!("Error: {} output requires wfm_reader to be built with the 'arrow' feature", format);
+            process::exit(1);
+        }
+        #[cfg(feature = "xlsx")]
+        "xlsx" => wfm.write_xlsx(output_file),
+        #[cfg(not(feature = "xlsx"))]
+        "xlsx" => {
+            eprintln!("Error: xlsx output requires wfm_reader to be built with the 'xlsx' feature");
+            process::exit(1);
+        }
+        other => {
+            eprintln!("Error: Unknown output format '{}'", other);
+            print_usage();
+            process::exit(1);
+        }
+    }
+}
+
+/// Frame indices from `--frames`, a comma-separated list of indices and
+/// `start..end` ranges, or every frame if the option is absent
+fn frame_list(args: &[String], num_frames: u32) -> Vec<u32> {
+    let Some(list) = option_value(args, "--frames") else {
+        return (0..num_frames).collect();
+    };
+    
+    let invalid = |part: &str| -> ! {
+        eprintln!("Error: Invalid frame selection '{}'", part);
+        process::exit(1);
+    };
+    let mut frames = Vec::new();
+    for part in list.split(',').map(str::trim) {
+        match part.split_once("..") {
+            Some((start, end)) => {
+                let start: u32 = start.parse().unwrap_or_else(|_| invalid(part));
+                let end: u32 = if end.is_empty() { num_frames } else { end.parse().unwrap_or_else(|_| invalid(part)) };
+                frames.extend(start..end);
+            }
+            None => frames.push(part.parse().unwrap_or_else(|_| invalid(part))),
+        }
+    }
+    frames
+}
+
+/// Filter described by the filter options, exiting if none is given
+fn build_filter(args: &[String], sample_rate: f64) -> wfm_reader::Result<Filter> {
+    if let Some(length) = parse_option::<usize>(args, "--moving-average") {
+        return Filter::moving_average(length);
+    }
+    if let Some((window, order)) = option_value(args, "--savgol").and_then(|v| v.split_once(',')) {
+        let parse = |v: &str| v.trim().parse::<usize>().unwrap_or_else(|_| {
+            eprintln!("Error: Invalid value '{}' for --savgol", v);
+            process::exit(1);
+        });
+        return Filter::savitzky_golay(parse(window), parse(order));
+    }
+    if let Some(frequency) = parse_option::<f64>(args, "--notch") {
+        return Filter::notch(frequency, parse_option(args, "--q").unwrap_or(30.0), sample_rate);
+    }
+    
+    let pair = |name: &str| option_value(args, name).map(|v| {
+        let values: Vec<f64> = v.split(',').filter_map(|f| f.trim().parse().ok()).collect();
+        if values.len() != 2 {
+            eprintln!("Error: {} needs two comma-separated frequencies", name);
+            process::exit(1);
+        }
+        (values[0], values[1])
+    });
+    let band = if let Some(f) = parse_option(args, "--lowpass") {
+        Band::LowPass(f)
+    } else if let Some(f) = parse_option(args, "--highpass") {
+        Band::HighPass(f)
+    } else if let Some((low, high)) = pair("--bandpass") {
+        Band::BandPass(low, high)
+    } else if let Some((low, high)) = pair("--bandstop") {
+        Band::BandStop(low, high)
+    } else {
+        eprintln!("Error: Missing filter, e.g. --lowpass <f> or --moving-average <n>");
+        process::exit(1);
+    };
+    
+    match option_value(args, "--design").unwrap_or("butterworth") {
+        "butterworth" => Filter::butterworth(parse_option(args, "--order").unwrap_or(4), band, sample_rate),
+        "chebyshev" => Filter::chebyshev(parse_option(args, "--order").unwrap_or(4),
+                                         parse_option(args, "--ripple").unwrap_or(1.0), band, sample_rate),
+        "fir" => Filter::windowed_sinc(parse_option(args, "--order").unwrap_or(101), band, sample_rate),
+        other => {
+            eprintln!("Error: Unknown filter design '{}'", other);
+            process::exit(1);
+        }
+    }
+}
+
+/// Baseline window from `start..end` in samples, or in seconds with an `s` suffix
+fn baseline_window(spec: &str) -> BaselineWindow {
+    let invalid = || -> ! {
+        eprintln!("Error: Invalid baseline window '{}'", spec);
+        process::exit(1);
+    };
+    let Some((start, end)) = spec.split_once("..") else { invalid() };
+    match end.strip_suffix('s') {
+        Some(end) => {
+            let start = start.trim_end_matches('s').parse().unwrap_or_else(|_| invalid());
+            BaselineWindow::Time(start..end.parse().unwrap_or_else(|_| invalid()))
+        }
+        None => BaselineWindow::Samples(start.parse().unwrap_or_else(|_| invalid())..end.parse().unwrap_or_else(|_| invalid())),
+    }
+}
+
+/// Parse a numeric option value, exiting with a message if it is malformed
+fn parse_option<T: std::str::FromStr>(args: &[String], name: &str) -> Option<T> {
+    option_value(args, name).map(|value| value.parse().unwrap_or_else(|_| {
+        eprintln!("Error: Invalid value '{}' for {}", value, name);
+        process::exit(1);
+    }))
+}
+
+/// WAV options from the command line
+fn wav_options(args: &[String]) -> WavOptions {
+    let mut options = WavOptions::new()
+        .normalize(args.iter().any(|a| a == "--normalize"));
+    
+    options.frame = parse_option(args, "--frame");
+    options.sample_rate = parse_option(args, "--rate");
+    if let Some(gap) = parse_option(args, "--gap") {
+        options = options.gap_samples(gap);
+    }
+    
+    match option_value(args, "--wav-format") {
+        None | Some("pcm16") => {}
+        Some("pcm32") => options = options.format(WavFormat::Pcm32),
+        Some("float") => options = options.format(WavFormat::Float32),
+        Some(other) => {
+            eprintln!("Error: Unknown WAV format '{}'", other);
+            process::exit(1);
+        }
+    }
+    
+    options
+}
+
+/// Output format implied by a file extension, defaulting to CSV
+fn format_from_extension(output_file: &str) -> String {
+    Path::new(output_file)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .map(|e| match e.as_str() {
+            "arrow" | "feather" | "ipc" => "arrow".to_string(),
+            _ => e,
+        })
+        .filter(|e| matches!(e.as_str(), "npy" | "npz" | "mat" | "bin" | "wav" | "tdms" | "parquet" | "arrow" | "xlsx"))
+        .unwrap_or_else(|| "csv".to_string())
+}
+
+/// Table layout selected with `--layout`, defaulting to long
+#[cfg(feature = "arrow")]
+fn table_layout(args: &[String]) -> wfm_reader::TableLayout {
+    match option_value(args, "--layout") {
+        None | Some("long") => wfm_reader::TableLayout::Long,
+        Some("wide") => wfm_reader::TableLayout::Wide,
+        Some(other) => {
+            eprintln!("Error: Unknown table layout '{}'", other);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+fn print_header_json(wfm: &WfmFile) {
+    match serde_json::to_string_pretty(&wfm.file_header) {
+        Ok(json) => println!("{}", json),
+        Err(e) => {
+            eprintln!("Error serializing header: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(feature = "serde"))]
+fn print_header_json(_wfm: &WfmFile) {
+    eprintln!("Error: JSON output requires wfm_reader to be built with the 'serde' feature");
+    process::exit(1);
+}
+
 fn print_file_info(wfm: &WfmFile) {
     println!("WFM File Information");
     println!("====================");
@@ -132,7 +1053,7 @@ fn print_file_info(wfm: &WfmFile) {
     println!("  Number of frames: {}", wfm.file_header.num_fastframes);
     println!("  Samples per frame: {}", wfm.file_header.full_record_length);
     println!("  Usable samples: {}", wfm.file_header.usable_record_length);
-    println!("  Total samples: {}", wfm.file_content.scaled_frames.len());
+    println!("  Total samples: {}", wfm.total_samples());
     println!();
     
     println!("Voltage Scaling:");
@@ -140,14 +1061,10 @@ fn print_file_info(wfm: &WfmFile) {
     println!("  Offset: {} V", wfm.file_header.voltage_offset);
     
     // Calculate voltage range from data
-    if !wfm.file_content.scaled_frames.is_empty() {
-        let min_v = wfm.file_content.scaled_frames.iter()
-            .fold(f64::INFINITY, |a, &b| a.min(b));
-        let max_v = wfm.file_content.scaled_frames.iter()
-            .fold(f64::NEG_INFINITY, |a, &b| a.max(b));
-        
-        println!("  Data range: {:.3} V to {:.3} V", min_v, max_v);
-        println!("  Peak-to-peak: {:.3} V", max_v - min_v);
+    let stats = wfm.stats().ok();
+    if let Some(overall) = stats.as_ref().map(|s| s.overall).filter(|s| s.count > 0) {
+        println!("  Data range: {:.3} V to {:.3} V", overall.min, overall.max);
+        println!("  Peak-to-peak: {:.3} V", overall.peak_to_peak);
     }
     println!();
     
@@ -170,15 +1087,48 @@ fn print_file_info(wfm: &WfmFile) {
     
     // Show statistics for first few frames
     println!("Frame Statistics (first {} frames):", 3.min(wfm.file_header.num_fastframes));
-    for i in 0..wfm.file_header.num_fastframes.min(3) {
-        if let Some(frame) = wfm.get_frame(i) {
-            let min = frame.iter().fold(f64::INFINITY, |a, &b| a.min(b));
-            let max = frame.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
-            let avg = frame.iter().sum::<f64>() / frame.len() as f64;
-            let rms = (frame.iter().map(|&x| x * x).sum::<f64>() / frame.len() as f64).sqrt();
-            
-            println!("  Frame {}: min={:.3}V, max={:.3}V, avg={:.3}V, rms={:.3}V", 
-                     i, min, max, avg, rms);
+    for (i, frame) in stats.iter().flat_map(|s| s.frames.iter().take(3)).enumerate() {
+        println!("  Frame {}: min={:.3}V, max={:.3}V, avg={:.3}V, rms={:.3}V", 
+                 i, frame.min, frame.max, frame.mean, frame.rms);
+    }
+}
+
+/// Print capture statistics as an aligned table with one row per frame
+fn print_stats_table(stats: &CaptureStats) {
+    println!("{:>6} {:>12} {:>12} {:>12} {:>12} {:>12} {:>12} {:>8} {:>8}",
+             "Frame", "Min", "Max", "Mean", "RMS", "StdDev", "P2P", "ArgMin", "ArgMax");
+    let rows = stats.frames.iter()
+        .enumerate()
+        .map(|(i, s)| (i.to_string(), s))
+        .chain(std::iter::once(("all".to_string(), &stats.overall)));
+    for (frame, s) in rows {
+        println!("{:>6} {:>12.4e} {:>12.4e} {:>12.4e} {:>12.4e} {:>12.4e} {:>12.4e} {:>8} {:>8}",
+                 frame, s.min, s.max, s.mean, s.rms, s.std_dev, s.peak_to_peak, s.argmin, s.argmax);
+    }
+}
+
+/// Print every peak, one row per peak
+fn print_peak_table(report: &PeakReport) {
+    println!("{:>6} {:>8} {:>12} {:>12} {:>12} {:>12} {:>9}",
+             "Frame", "Index", "Time", "Value", "Prominence", "Width", "Polarity");
+    for (frame, peaks) in report.frames.iter().enumerate() {
+        for p in peaks {
+            println!("{:>6} {:>8} {:>12.4e} {:>12.4e} {:>12.4e} {:>12.4e} {:>9}",
+                     frame, p.index, p.time, p.value, p.prominence, p.width, if p.minimum { "-" } else { "+" });
         }
     }
+    println!("{} peaks in {} frames", report.total(), report.frames.len());
+}
+
+/// Print the statistics of each measurement across frames
+fn print_measurement_table(statistics: Vec<(&str, FrameStats)>, unit: fn(&str) -> &'static str) {
+    println!("{:<26} {:>12} {:>12} {:>12} {:>12} {:>7}", "Measurement", "Mean", "Min", "Max", "StdDev", "Frames");
+    for (name, s) in statistics {
+        let label = match unit(name) {
+            "" => name.to_string(),
+            unit => format!("{} ({})", name, unit),
+        };
+        println!("{:<26} {:>12.4e} {:>12.4e} {:>12.4e} {:>12.4e} {:>7}",
+                 label, s.mean, s.min, s.max, s.std_dev, s.count);
+    }
 }
\ No newline at end of file
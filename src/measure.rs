@@ -0,0 +1,397 @@
+// Pulse Measurements
+// IEEE 181-style amplitude and transition measurements per frame
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::{FrameStats, Result, Waveform};
+
+/// Number of histogram bins used to find the top and base levels
+const HISTOGRAM_BINS: usize = 256;
+
+/// Share of a histogram half its mode bin must hold to count as a state level
+const MIN_MODE_SHARE: f64 = 0.05;
+
+/// Reference levels used by [`measure_pulse`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MeasureOptions {
+    /// Low reference level as a fraction of the amplitude above base
+    pub low_ref: f64,
+    /// Middle reference level as a fraction of the amplitude above base
+    pub mid_ref: f64,
+    /// High reference level as a fraction of the amplitude above base
+    pub high_ref: f64,
+    /// Settling tolerance as a fraction of the amplitude
+    pub settling_band: f64,
+}
+
+impl Default for MeasureOptions {
+    fn default() -> Self {
+        MeasureOptions {
+            low_ref: 0.1,
+            mid_ref: 0.5,
+            high_ref: 0.9,
+            settling_band: 0.02,
+        }
+    }
+}
+
+impl MeasureOptions {
+    /// 10 %, 50 % and 90 % reference levels with a 2 % settling band
+    pub fn new() -> Self {
+        MeasureOptions::default()
+    }
+
+    /// Set the low and high reference levels, e.g. `0.2, 0.8`
+    pub fn reference_levels(mut self, low_ref: f64, high_ref: f64) -> Self {
+        self.low_ref = low_ref;
+        self.high_ref = high_ref;
+        self
+    }
+
+    /// Set the middle reference level used for widths and settling
+    pub fn mid_ref(mut self, mid_ref: f64) -> Self {
+        self.mid_ref = mid_ref;
+        self
+    }
+
+    /// Set the settling tolerance as a fraction of the amplitude
+    pub fn settling_band(mut self, settling_band: f64) -> Self {
+        self.settling_band = settling_band;
+        self
+    }
+}
+
+/// Pulse measurements of one frame
+///
+/// Times are in the source's horizontal units and measured on the first
+/// matching edge of the frame; they are `None` when the frame has no such
+/// edge. Overshoot and undershoot are percentages of the amplitude.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PulseMeasurements {
+    /// High state level
+    pub top: f64,
+    /// Low state level
+    pub base: f64,
+    /// `top - base`
+    pub amplitude: f64,
+    /// Time from the low to the high reference level on a rising edge
+    pub rise_time: Option<f64>,
+    /// Time from the high to the low reference level on a falling edge
+    pub fall_time: Option<f64>,
+    /// Time from a rising to the next falling middle reference crossing
+    pub positive_width: Option<f64>,
+    /// Time from a falling to the next rising middle reference crossing
+    pub negative_width: Option<f64>,
+    /// `(max - top) / amplitude` in percent
+    pub overshoot: f64,
+    /// `(base - min) / amplitude` in percent
+    pub undershoot: f64,
+    /// Time from the first middle reference crossing until the signal stays
+    /// within the settling band around its new state, up to the point where
+    /// it heads back past the high (or low) reference level
+    pub settling_time: Option<f64>,
+}
+
+impl PulseMeasurements {
+    /// Names of the measurements, in declaration order
+    pub const FIELD_NAMES: [&'static str; 10] = [
+        "top", "base", "amplitude", "rise_time", "fall_time", "positive_width",
+        "negative_width", "overshoot", "undershoot", "settling_time",
+    ];
+
+    /// All measurements as name/value pairs, in declaration order
+    pub fn fields(&self) -> [(&'static str, Option<f64>); 10] {
+        let values = [
+            Some(self.top), Some(self.base), Some(self.amplitude), self.rise_time, self.fall_time,
+            self.positive_width, self.negative_width, Some(self.overshoot), Some(self.undershoot),
+            self.settling_time,
+        ];
+        std::array::from_fn(|i| (Self::FIELD_NAMES[i], values[i]))
+    }
+}
+
+/// Pulse measurements of every frame of a capture
+#[derive(Clone, Debug, PartialEq)]
+pub struct PulseReport {
+    /// One entry per frame, in frame order
+    pub frames: Vec<PulseMeasurements>,
+}
+
+impl PulseReport {
+    /// Statistics of each measurement across the frames that have it
+    pub fn statistics(&self) -> Vec<(&'static str, FrameStats)> {
+        field_statistics(&PulseMeasurements::FIELD_NAMES, self.frames.iter().map(|m| m.fields().to_vec()))
+    }
+
+    /// Write one CSV row of measurements per frame
+    pub fn write_csv<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        let file = File::create(output_file)?;
+        let mut writer = BufWriter::new(file);
+
+        self.write_csv_to(&mut writer)?;
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the per-frame measurements as CSV to any writer
+    ///
+    /// Missing measurements are left empty.
+    pub fn write_csv_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        write_fields_csv(writer, &PulseMeasurements::FIELD_NAMES, self.frames.iter().map(|m| m.fields().to_vec()))
+    }
+}
+
+/// Statistics of each named value across frames, skipping missing values
+///
+/// Every frame must list `names` in the same order.
+pub(crate) fn field_statistics<I>(names: &[&'static str], frames: I) -> Vec<(&'static str, FrameStats)>
+where
+    I: IntoIterator<Item = Vec<(&'static str, Option<f64>)>>,
+{
+    let mut columns: Vec<Vec<f64>> = vec![Vec::new(); names.len()];
+    for fields in frames {
+        for (column, (_, value)) in columns.iter_mut().zip(fields) {
+            column.push(value.unwrap_or(f64::NAN));
+        }
+    }
+    names.iter()
+        .zip(columns)
+        .map(|(&name, values)| (name, FrameStats::from_samples(&values)))
+        .collect()
+}
+
+/// Write named per-frame values as CSV with a leading `frame` column
+///
+/// The header row is written even when there are no frames.
+pub(crate) fn write_fields_csv<W, I>(writer: &mut W, names: &[&str], frames: I) -> Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = Vec<(&'static str, Option<f64>)>>,
+{
+    writeln!(writer, "frame,{}", names.join(","))?;
+    for (i, fields) in frames.into_iter().enumerate() {
+        let values: Vec<String> = fields.iter()
+            .map(|(_, v)| v.map(|v| v.to_string()).unwrap_or_default())
+            .collect();
+        writeln!(writer, "{},{}", i, values.join(","))?;
+    }
+    Ok(())
+}
+
+/// Top and base state levels by the histogram mode method of IEEE 181
+///
+/// The histogram between minimum and maximum is split in half and the
+/// most populated bin of each half gives a state level. A half without a
+/// clear mode, such as on a triangle wave, falls back to the extreme value.
+pub fn top_base(samples: &[f64]) -> (f64, f64) {
+    let (min, max) = samples.iter()
+        .filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    if max <= min {
+        return (max, min);
+    }
+
+    let width = (max - min) / HISTOGRAM_BINS as f64;
+    let bin = |v: f64| (((v - min) / width) as usize).min(HISTOGRAM_BINS - 1);
+    let mut counts = [0usize; HISTOGRAM_BINS];
+    for &v in samples.iter().filter(|v| v.is_finite()) {
+        counts[bin(v)] += 1;
+    }
+
+    // Mean of the samples in the mode bin of a half, or the fallback level.
+    // Bins run from the centre outwards so ties go to the outermost bin.
+    let level = |bins: Vec<usize>, fallback: f64| {
+        let total: usize = bins.iter().map(|&b| counts[b]).sum();
+        let mode = bins.into_iter().max_by_key(|&b| counts[b]).unwrap();
+        if (counts[mode] as f64) < MIN_MODE_SHARE * total as f64 || counts[mode] == 0 {
+            return fallback;
+        }
+        let in_mode: Vec<f64> = samples.iter().copied().filter(|v| v.is_finite() && bin(*v) == mode).collect();
+        in_mode.iter().sum::<f64>() / in_mode.len() as f64
+    };
+
+    let half = HISTOGRAM_BINS / 2;
+    (level((half..HISTOGRAM_BINS).collect(), max), level((0..half).rev().collect(), min))
+}
+
+/// Interpolated sample position where the signal crosses `level` between `i - 1` and `i`
+fn crossing(samples: &[f64], i: usize, level: f64) -> f64 {
+    let (a, b) = (samples[i - 1], samples[i]);
+    (i - 1) as f64 + (level - a) / (b - a)
+}
+
+/// Positions of the crossings of `level` in one direction, starting at sample `from`
+fn crossings(samples: &[f64], level: f64, rising: bool, from: usize) -> impl Iterator<Item = f64> + '_ {
+    (from.max(1)..samples.len())
+        .filter(move |&i| {
+            let (a, b) = (samples[i - 1], samples[i]);
+            if rising { a < level && b >= level } else { a > level && b <= level }
+        })
+        .map(move |i| crossing(samples, i, level))
+}
+
+/// Duration of the first complete transition from `start` to `end` level
+///
+/// The transition must reach `end` before the signal falls back past `start`.
+fn transition_time(samples: &[f64], start: f64, end: f64) -> Option<f64> {
+    let rising = end > start;
+    let past = |v: f64, level: f64| if rising { v >= level } else { v <= level };
+
+    let mut begin = None;
+    for i in 1..samples.len() {
+        let (a, b) = (samples[i - 1], samples[i]);
+        if !past(a, start) && past(b, start) {
+            begin = Some(crossing(samples, i, start));
+        } else if past(a, start) && !past(b, start) {
+            begin = None;
+        }
+        if let Some(t) = begin {
+            if !past(a, end) && past(b, end) {
+                return Some(crossing(samples, i, end) - t);
+            }
+        }
+    }
+    None
+}
+
+/// Measure the pulse parameters of one frame sampled every `sample_interval`
+pub fn measure_pulse(samples: &[f64], sample_interval: f64, options: &MeasureOptions) -> PulseMeasurements {
+    let (top, base) = top_base(samples);
+    let amplitude = top - base;
+    let (min, max) = samples.iter()
+        .filter(|v| v.is_finite())
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+
+    let mut m = PulseMeasurements {
+        top,
+        base,
+        amplitude,
+        overshoot: f64::NAN,
+        undershoot: f64::NAN,
+        ..PulseMeasurements::default()
+    };
+    if amplitude.is_nan() || amplitude <= 0.0 {
+        return m;
+    }
+
+    let low = base + options.low_ref * amplitude;
+    let mid = base + options.mid_ref * amplitude;
+    let high = base + options.high_ref * amplitude;
+    let time = |samples: f64| samples * sample_interval;
+
+    m.overshoot = (max - top) / amplitude * 100.0;
+    m.undershoot = (base - min) / amplitude * 100.0;
+    m.rise_time = transition_time(samples, low, high).map(time);
+    m.fall_time = transition_time(samples, high, low).map(time);
+
+    let first_rise = crossings(samples, mid, true, 0).next();
+    let first_fall = crossings(samples, mid, false, 0).next();
+    m.positive_width = first_rise
+        .and_then(|r| crossings(samples, mid, false, r.ceil() as usize).next().map(|f| time(f - r)));
+    m.negative_width = first_fall
+        .and_then(|f| crossings(samples, mid, true, f.ceil() as usize).next().map(|r| time(r - f)));
+
+    // Settle to the new state before the signal heads back past the far
+    // reference level towards the next edge
+    let first_edge = match (first_rise, first_fall) {
+        (Some(r), Some(f)) if f < r => Some((f, false)),
+        (Some(r), _) => Some((r, true)),
+        (None, Some(f)) => Some((f, false)),
+        (None, None) => None,
+    };
+    m.settling_time = first_edge.and_then(|(edge, rising)| {
+        let start = edge.ceil() as usize;
+        let far = if rising { high } else { low };
+        let end = crossings(samples, far, !rising, start).next()
+            .map_or(samples.len(), |c| c.ceil() as usize);
+        let target = if rising { top } else { base };
+        let band = options.settling_band * amplitude;
+        let last_outside = (start..end).rev().find(|&i| (samples[i] - target).abs() > band);
+        match last_outside {
+            Some(i) if i + 1 >= end => None,
+            Some(i) => Some(time((i + 1) as f64 - edge)),
+            None => Some(time(start as f64 - edge)),
+        }
+    });
+
+    m
+}
+
+/// Pulse measurements of every frame, reading each frame once
+pub(crate) fn measure_frames<S: Waveform + ?Sized>(source: &S, options: &MeasureOptions) -> Result<PulseReport> {
+    let frames = (0..source.num_frames())
+        .map(|i| Ok(measure_pulse(&source.try_frame(i)?, source.sample_interval(), options)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(PulseReport { frames })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Analysis, WfmFile};
+
+    /// Pulse from 0 to 1 with a 10-sample ramp up at 20, a 10 % overshoot at
+    /// sample 31 and a 10-sample ramp down at 70
+    fn pulse() -> Vec<f64> {
+        (0..100)
+            .map(|i| match i {
+                0..=20 => 0.0,
+                21..=29 => (i - 20) as f64 / 10.0,
+                31 => 1.1,
+                30..=70 => 1.0,
+                71..=79 => (80 - i) as f64 / 10.0,
+                _ => 0.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_measure_pulse() {
+        let m = measure_pulse(&pulse(), 1e-9, &MeasureOptions::new());
+        assert_eq!((m.top, m.base, m.amplitude), (1.0, 0.0, 1.0));
+        assert!((m.rise_time.unwrap() - 8e-9).abs() < 1e-15);
+        assert!((m.fall_time.unwrap() - 8e-9).abs() < 1e-15);
+        assert!((m.positive_width.unwrap() - 50e-9).abs() < 1e-15);
+        assert_eq!(m.negative_width, None);
+        assert!((m.overshoot - 10.0).abs() < 1e-9);
+        assert_eq!(m.undershoot, 0.0);
+        assert!((m.settling_time.unwrap() - 7e-9).abs() < 1e-15);
+
+        let m = measure_pulse(&pulse(), 1e-9, &MeasureOptions::new().reference_levels(0.2, 0.8));
+        assert!((m.rise_time.unwrap() - 6e-9).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_measure_frames() {
+        let mut samples = pulse();
+        samples.extend(pulse().iter().map(|v| 2.0 * v));
+        let wfm = WfmFile::from_samples(100, 1e-9, 0.0, samples).unwrap();
+
+        let report = wfm.measure_pulses(&MeasureOptions::new()).unwrap();
+        assert_eq!(report.frames.len(), 2);
+        assert_eq!(report.frames[1].amplitude, 2.0);
+
+        let stats = report.statistics();
+        let (name, amplitude) = stats[2];
+        assert_eq!(name, "amplitude");
+        assert_eq!((amplitude.mean, amplitude.count), (1.5, 2));
+        assert_eq!(stats[6].1.count, 0);
+
+        let mut csv = Vec::new();
+        report.write_csv_to(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("frame,top,base,amplitude,rise_time,"));
+        assert_eq!(csv.lines().count(), 3);
+
+        // An empty report still names its columns
+        let empty = PulseReport { frames: Vec::new() };
+        let mut csv = Vec::new();
+        empty.write_csv_to(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(),
+                   format!("frame,{}\n", PulseMeasurements::FIELD_NAMES.join(",")));
+        assert_eq!(empty.statistics().len(), 10);
+    }
+}
@@ -0,0 +1,358 @@
+// Peak Detection
+// Local maxima and minima filtered by height, prominence, width and separation
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::{Result, Waveform};
+
+/// Which extrema count as peaks
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Polarity {
+    /// Local maxima
+    #[default]
+    Positive,
+    /// Local minima
+    Negative,
+    /// Both maxima and minima
+    Both,
+}
+
+impl Polarity {
+    /// Parse a polarity name as used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "positive" | "max" | "maxima" => Some(Polarity::Positive),
+            "negative" | "min" | "minima" => Some(Polarity::Negative),
+            "both" => Some(Polarity::Both),
+            _ => None,
+        }
+    }
+}
+
+/// Filters used by [`find_peaks`]
+///
+/// Every limit is optional. For minima, height and prominence apply to the
+/// inverted signal, so a minimum passes `height` when it lies at or below
+/// `-height`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PeakOptions {
+    pub polarity: Polarity,
+    /// Minimum peak value
+    pub height: Option<f64>,
+    /// Minimum height above the higher of the two surrounding bases
+    pub prominence: Option<f64>,
+    /// Minimum width at half prominence, in horizontal units
+    pub width: Option<f64>,
+    /// Minimum time between peaks of the same polarity; the lower of two
+    /// closer peaks is dropped
+    pub separation: Option<f64>,
+}
+
+impl PeakOptions {
+    /// Every local maximum, unfiltered
+    pub fn new() -> Self {
+        PeakOptions::default()
+    }
+
+    /// Choose maxima, minima or both
+    pub fn polarity(mut self, polarity: Polarity) -> Self {
+        self.polarity = polarity;
+        self
+    }
+
+    /// Set the minimum peak value
+    pub fn height(mut self, height: f64) -> Self {
+        self.height = Some(height);
+        self
+    }
+
+    /// Set the minimum prominence
+    pub fn prominence(mut self, prominence: f64) -> Self {
+        self.prominence = Some(prominence);
+        self
+    }
+
+    /// Set the minimum width at half prominence, in horizontal units
+    pub fn width(mut self, width: f64) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    /// Set the minimum time between peaks, in horizontal units
+    pub fn separation(mut self, separation: f64) -> Self {
+        self.separation = Some(separation);
+        self
+    }
+}
+
+/// A peak located with parabolic sub-sample interpolation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Peak {
+    /// Index of the peak sample, the middle one for a flat top
+    pub index: usize,
+    /// Time of the interpolated vertex on the frame's time axis
+    pub time: f64,
+    /// Interpolated peak value in vertical units
+    pub value: f64,
+    /// Height above the higher of the two surrounding bases, always positive
+    pub prominence: f64,
+    /// Width at half prominence in horizontal units
+    pub width: f64,
+    /// Whether this is a local minimum
+    pub minimum: bool,
+}
+
+/// Peaks of every frame of a capture
+#[derive(Clone, Debug, PartialEq)]
+pub struct PeakReport {
+    /// Peaks of each frame in time order, one entry per frame
+    pub frames: Vec<Vec<Peak>>,
+}
+
+impl PeakReport {
+    /// Number of peaks across all frames
+    pub fn total(&self) -> usize {
+        self.frames.iter().map(Vec::len).sum()
+    }
+
+    /// Write one CSV row per peak
+    pub fn write_csv<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        let file = File::create(output_file)?;
+        let mut writer = BufWriter::new(file);
+
+        self.write_csv_to(&mut writer)?;
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the peaks as CSV to any writer
+    pub fn write_csv_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(writer, "frame,index,time,value,prominence,width,polarity")?;
+        for (frame, peaks) in self.frames.iter().enumerate() {
+            for p in peaks {
+                writeln!(writer, "{},{},{},{},{},{},{}", frame, p.index, p.time, p.value, p.prominence, p.width,
+                         if p.minimum { "negative" } else { "positive" })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Peaks of one frame with first sample at `t0`, sampled every `sample_interval`
+pub fn find_peaks(samples: &[f64], sample_interval: f64, t0: f64, options: &PeakOptions) -> Vec<Peak> {
+    let mut peaks = Vec::new();
+    if matches!(options.polarity, Polarity::Positive | Polarity::Both) {
+        peaks.extend(oriented_peaks(samples, sample_interval, t0, options, false));
+    }
+    if matches!(options.polarity, Polarity::Negative | Polarity::Both) {
+        let inverted: Vec<f64> = samples.iter().map(|v| -v).collect();
+        peaks.extend(oriented_peaks(&inverted, sample_interval, t0, options, true));
+    }
+    peaks.sort_by_key(|p| p.index);
+    peaks
+}
+
+/// Maxima of `x`, negated back when `minimum` is set
+fn oriented_peaks(x: &[f64], sample_interval: f64, t0: f64, options: &PeakOptions, minimum: bool) -> Vec<Peak> {
+    let mut candidates: Vec<usize> = local_maxima(x)
+        .into_iter()
+        .filter(|&i| options.height.is_none_or(|h| x[i] >= h))
+        .collect();
+
+    if let Some(separation) = options.separation {
+        let distance = separation / sample_interval;
+        let mut by_height = candidates.clone();
+        by_height.sort_by(|&a, &b| x[b].total_cmp(&x[a]).then(a.cmp(&b)));
+        let mut kept: Vec<usize> = Vec::new();
+        for i in by_height {
+            if kept.iter().all(|&k| (k as f64 - i as f64).abs() >= distance) {
+                kept.push(i);
+            }
+        }
+        kept.sort_unstable();
+        candidates = kept;
+    }
+
+    let sign = if minimum { -1.0 } else { 1.0 };
+    candidates.into_iter()
+        .filter_map(|i| {
+            let (prominence, left_base, right_base) = prominence(x, i);
+            if options.prominence.is_some_and(|p| prominence < p) {
+                return None;
+            }
+            let width = half_width(x, i, prominence, left_base, right_base) * sample_interval;
+            if options.width.is_some_and(|w| width < w) {
+                return None;
+            }
+            let (offset, value) = parabolic_vertex(x, i);
+            Some(Peak {
+                index: i,
+                time: t0 + (i as f64 + offset) * sample_interval,
+                value: sign * value,
+                prominence,
+                width,
+                minimum,
+            })
+        })
+        .collect()
+}
+
+/// Indices of samples higher than both neighbours, taking the middle of flat tops
+fn local_maxima(x: &[f64]) -> Vec<usize> {
+    let mut maxima = Vec::new();
+    let mut i = 1;
+    while i + 1 < x.len() {
+        if x[i - 1] < x[i] {
+            let mut end = i;
+            while end + 1 < x.len() && x[end + 1] == x[i] {
+                end += 1;
+            }
+            if end + 1 < x.len() && x[end + 1] < x[i] {
+                maxima.push((i + end) / 2);
+            }
+            i = end + 1;
+        } else {
+            i += 1;
+        }
+    }
+    maxima
+}
+
+/// Prominence of the peak at `i` and the indices of its left and right bases
+///
+/// Each base is the lowest sample between the peak and the nearest higher
+/// sample on that side, or the end of the frame.
+fn prominence(x: &[f64], i: usize) -> (f64, usize, usize) {
+    let mut left_base = i;
+    for j in (0..i).rev() {
+        if x[j] > x[i] {
+            break;
+        }
+        if x[j] < x[left_base] {
+            left_base = j;
+        }
+    }
+    let mut right_base = i;
+    for j in i + 1..x.len() {
+        if x[j] > x[i] {
+            break;
+        }
+        if x[j] < x[right_base] {
+            right_base = j;
+        }
+    }
+    (x[i] - x[left_base].max(x[right_base]), left_base, right_base)
+}
+
+/// Width in samples where the peak crosses half its prominence, limited to its bases
+fn half_width(x: &[f64], i: usize, prominence: f64, left_base: usize, right_base: usize) -> f64 {
+    let level = x[i] - prominence / 2.0;
+    let crossing = |a: usize, b: usize| a as f64 + (level - x[a]) / (x[b] - x[a]) * (b as f64 - a as f64);
+
+    let left = (left_base..i).rev()
+        .find(|&j| x[j] <= level)
+        .map_or(left_base as f64, |j| crossing(j, j + 1));
+    let right = (i + 1..=right_base)
+        .find(|&j| x[j] <= level)
+        .map_or(right_base as f64, |j| crossing(j, j - 1));
+    right - left
+}
+
+/// Offset from `i` and value of the parabola through the peak and its neighbours
+///
+/// Flat tops report the plateau value at the middle of the plateau instead.
+fn parabolic_vertex(x: &[f64], i: usize) -> (f64, f64) {
+    let (a, b, c) = (x[i - 1], x[i], x[i + 1]);
+    if a == b || c == b {
+        let start = (0..i).rev().take_while(|&j| x[j] == b).last().unwrap_or(i);
+        let end = (i + 1..x.len()).take_while(|&j| x[j] == b).last().unwrap_or(i);
+        return ((start + end) as f64 / 2.0 - i as f64, b);
+    }
+    let curvature = a - 2.0 * b + c;
+    if curvature >= 0.0 {
+        return (0.0, b);
+    }
+    let offset = (0.5 * (a - c) / curvature).clamp(-0.5, 0.5);
+    (offset, b - 0.25 * (a - c) * offset)
+}
+
+/// Peaks of every frame, reading each frame once
+pub(crate) fn find_peaks_frames<S: Waveform + ?Sized>(source: &S, options: &PeakOptions) -> Result<PeakReport> {
+    let frames = (0..source.num_frames())
+        .map(|i| Ok(find_peaks(&source.try_frame(i)?, source.sample_interval(), source.t0(), options)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(PeakReport { frames })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Analysis, WfmFile};
+
+    #[test]
+    fn test_find_peaks() {
+        // Parabola with its vertex at sample 10.3, height 5
+        let samples: Vec<f64> = (0..21).map(|i| 5.0 - 0.1 * (i as f64 - 10.3).powi(2)).collect();
+        let peaks = find_peaks(&samples, 2.0, -20.0, &PeakOptions::new());
+        assert_eq!(peaks.len(), 1);
+        assert_eq!(peaks[0].index, 10);
+        assert!((peaks[0].time - (-20.0 + 2.0 * 10.3)).abs() < 1e-9);
+        assert!((peaks[0].value - 5.0).abs() < 1e-9);
+
+        // Flat tops report their middle sample, and ends never count
+        let plateau = [3.0, 0.0, 1.0, 2.0, 2.0, 2.0, 1.0, 0.0, 4.0];
+        let peaks = find_peaks(&plateau, 1.0, 0.0, &PeakOptions::new());
+        assert_eq!(peaks.iter().map(|p| p.index).collect::<Vec<_>>(), [4]);
+        assert_eq!(peaks[0].value, 2.0);
+        assert_eq!(peaks[0].prominence, 2.0);
+        assert_eq!(peaks[0].width, 4.0);
+        assert_eq!(peaks[0].time, 4.0);
+
+        // Even plateaus are located between their middle samples
+        for top in [2, 4] {
+            let mut samples = vec![0.0, 1.0];
+            samples.extend(vec![2.0; top]);
+            samples.extend([1.0, 0.0]);
+            let peaks = find_peaks(&samples, 1.0, 0.0, &PeakOptions::new());
+            assert_eq!(peaks.len(), 1);
+            assert_eq!(peaks[0].value, 2.0);
+            assert_eq!(peaks[0].time, 1.5 + top as f64 / 2.0);
+        }
+
+        let valleys = find_peaks(&plateau, 1.0, 0.0, &PeakOptions::new().polarity(Polarity::Both));
+        assert_eq!(valleys.iter().map(|p| (p.index, p.minimum)).collect::<Vec<_>>(),
+                   [(1, true), (4, false), (7, true)]);
+        assert_eq!(valleys[0].value, -0.125);
+        assert_eq!(Polarity::from_name("min"), Some(Polarity::Negative));
+    }
+
+    #[test]
+    fn test_peak_filters() {
+        // Echoes of 4, 1 and 3 at samples 10, 18 and 40 on a small ripple
+        let echo = |i: usize, at: usize, height: f64| height * (-((i as f64 - at as f64) / 1.5).powi(2)).exp();
+        let samples: Vec<f64> = (0..60)
+            .map(|i| echo(i, 10, 4.0) + echo(i, 18, 1.0) + echo(i, 40, 3.0) + 0.05 * (i as f64 * 2.5).sin())
+            .collect();
+        let wfm = WfmFile::from_samples(30, 1e-9, 0.0, samples).unwrap();
+
+        let index = |options: PeakOptions| -> Vec<Vec<usize>> {
+            wfm.find_peaks(&options).unwrap().frames.iter()
+                .map(|f| f.iter().map(|p| p.index).collect())
+                .collect()
+        };
+        assert!(wfm.find_peaks(&PeakOptions::new()).unwrap().total() > 3);
+        assert_eq!(index(PeakOptions::new().prominence(0.5)), [vec![10, 18], vec![10]]);
+        assert_eq!(index(PeakOptions::new().height(2.0)), [vec![10], vec![10]]);
+        assert_eq!(index(PeakOptions::new().prominence(0.5).separation(10e-9)), [vec![10], vec![10]]);
+        assert_eq!(index(PeakOptions::new().width(2e-9)), [vec![10, 18], vec![10]]);
+
+        let mut csv = Vec::new();
+        wfm.find_peaks(&PeakOptions::new().height(2.0)).unwrap().write_csv_to(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert!(csv.starts_with("frame,index,time,value,prominence,width,polarity\n0,10,"));
+        assert!(csv.lines().nth(2).unwrap().starts_with("1,10,"));
+    }
+}
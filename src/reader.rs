@@ -0,0 +1,136 @@
+// Lazy WFM Reader
+// Reads frames on demand instead of loading the whole file
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::{FrameTimestamp, Result, Waveform, WfmError, WfmHeader};
+
+/// WFM file reader that keeps only the header in memory
+///
+/// Frames are read from disk when requested, so memory use does not depend
+/// on the size of the capture. Use [`WfmFile`](crate::WfmFile) when all
+/// samples are needed at once.
+pub struct WfmReader {
+    pub file_path: String,
+    pub file_header: WfmHeader,
+    file: RefCell<File>,
+    curve_offset: u64,
+}
+
+impl WfmReader {
+    /// Open a WFM file and parse its header
+    ///
+    /// Fails if the file is too short to hold every frame the header announces.
+    pub fn open<P: AsRef<Path>>(input_file: P) -> Result<Self> {
+        let mut file = File::open(&input_file)?;
+
+        let mut header_buf = [0u8; 838];
+        file.read_exact(&mut header_buf)?;
+        let file_header = WfmHeader::parse_header(&header_buf)?;
+
+        let curve_offset = file_header.curve_data_offset();
+        let curve_end = curve_offset +
+                        file_header.full_record_length as u64 * file_header.num_fastframes as u64;
+        let file_len = file.metadata()?.len();
+        if file_len < curve_end {
+            return Err(WfmError::ParseError(
+                format!("Unexpected end of file: curve data ends at {} > file length {}",
+                        curve_end, file_len)
+            ));
+        }
+
+        Ok(WfmReader {
+            file_path: input_file.as_ref().to_string_lossy().to_string(),
+            file_header,
+            file: RefCell::new(file),
+            curve_offset,
+        })
+    }
+
+    /// Read the raw ADC codes of a frame
+    pub fn read_raw_frame(&self, frame_index: u32) -> Result<Vec<i8>> {
+        if frame_index >= self.file_header.num_fastframes {
+            return Err(WfmError::InvalidFrameRange(frame_index, frame_index + 1,
+                                                   self.file_header.num_fastframes));
+        }
+
+        let record_length = self.file_header.full_record_length as usize;
+        let mut buf = vec![0u8; record_length];
+
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(self.curve_offset + (record_length * frame_index as usize) as u64))?;
+        file.read_exact(&mut buf)?;
+
+        Ok(buf.into_iter().map(|b| b as i8).collect())
+    }
+
+    /// Read the scaled values of a frame
+    pub fn read_frame(&self, frame_index: u32) -> Result<Vec<f64>> {
+        Ok(self.read_raw_frame(frame_index)?
+            .into_iter()
+            .map(|v| self.file_header.scaled_value(v))
+            .collect())
+    }
+
+    /// Read the trigger timestamp of a frame
+    ///
+    /// Frame 0 is described by the main header, the others by the update
+    /// specifications that follow it.
+    pub fn read_frame_timestamp(&self, frame_index: u32) -> Result<FrameTimestamp> {
+        if frame_index >= self.file_header.num_fastframes {
+            return Err(WfmError::InvalidFrameRange(frame_index, frame_index + 1,
+                                                   self.file_header.num_fastframes));
+        }
+
+        let offset = match frame_index as usize {
+            0 => 0x310,
+            i => 838 + (i - 1) * FrameTimestamp::SPEC_SIZE,
+        };
+        let mut spec = [0u8; FrameTimestamp::SPEC_SIZE];
+
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(offset as u64))?;
+        file.read_exact(&mut spec)?;
+
+        FrameTimestamp::parse(&spec)
+    }
+}
+
+impl Waveform for WfmReader {
+    fn num_frames(&self) -> u32 {
+        self.file_header.num_fastframes
+    }
+
+    fn samples_per_frame(&self) -> usize {
+        self.file_header.full_record_length as usize
+    }
+
+    fn sample_interval(&self) -> f64 {
+        self.file_header.acq_time_scale
+    }
+
+    fn t0(&self) -> f64 {
+        self.file_header.acq_time_start
+    }
+
+    /// Reads the frame from disk; read errors are reported as `None`
+    fn frame(&self, index: u32) -> Option<Cow<'_, [f64]>> {
+        self.read_frame(index).ok().map(Cow::Owned)
+    }
+
+    fn raw_frame(&self, index: u32) -> Option<Cow<'_, [i8]>> {
+        self.read_raw_frame(index).ok().map(Cow::Owned)
+    }
+
+    fn frame_timestamp(&self, index: u32) -> Option<FrameTimestamp> {
+        self.read_frame_timestamp(index).ok()
+    }
+
+    fn source(&self) -> Option<(&str, &WfmHeader)> {
+        Some((&self.file_path, &self.file_header))
+    }
+}
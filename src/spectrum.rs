@@ -0,0 +1,401 @@
+// Frequency Spectra
+// Windowed FFT magnitude, phase and power spectral density per frame
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+use crate::export::{write_f64_npy, ZipWriter};
+use crate::{Result, Waveform, WfmError};
+
+/// Window applied to each frame before the FFT
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Window {
+    Rectangular,
+    #[default]
+    Hann,
+    /// 4-term Blackman-Harris, for low leakage
+    BlackmanHarris,
+    /// Flat-top, for accurate amplitudes between bins
+    FlatTop,
+}
+
+impl Window {
+    /// Parse a window name as used on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "rectangular" | "rect" | "none" => Some(Window::Rectangular),
+            "hann" | "hanning" => Some(Window::Hann),
+            "blackman-harris" | "blackmanharris" => Some(Window::BlackmanHarris),
+            "flat-top" | "flattop" => Some(Window::FlatTop),
+            _ => None,
+        }
+    }
+
+    /// Periodic window coefficients for `n` samples
+    pub fn coefficients(&self, n: usize) -> Vec<f64> {
+        let terms: &[f64] = match self {
+            Window::Rectangular => &[1.0],
+            Window::Hann => &[0.5, 0.5],
+            Window::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+            Window::FlatTop => &[0.21557895, 0.41663158, 0.277263158, 0.083578947, 0.006947368],
+        };
+        (0..n)
+            .map(|i| {
+                let x = 2.0 * std::f64::consts::PI * i as f64 / n as f64;
+                terms.iter()
+                    .enumerate()
+                    .map(|(k, a)| {
+                        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                        sign * a * (k as f64 * x).cos()
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+/// Settings used by [`Analysis::spectrum`](crate::Analysis::spectrum)
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SpectrumOptions {
+    pub window: Window,
+    /// FFT length; frames are zero-padded up to it. `None` uses the frame length
+    pub fft_length: Option<usize>,
+    /// Give magnitude in dBV and PSD in dB re 1 V²/Hz
+    pub decibels: bool,
+    /// Average all frames into a single spectrum
+    pub average: bool,
+}
+
+impl SpectrumOptions {
+    /// Hann window, no padding, linear units, one spectrum per frame
+    pub fn new() -> Self {
+        SpectrumOptions::default()
+    }
+
+    /// Set the window
+    pub fn window(mut self, window: Window) -> Self {
+        self.window = window;
+        self
+    }
+
+    /// Zero-pad every frame to `length` samples before the FFT
+    pub fn fft_length(mut self, length: usize) -> Self {
+        self.fft_length = Some(length);
+        self
+    }
+
+    /// Convert magnitude and PSD to decibels
+    pub fn decibels(mut self, decibels: bool) -> Self {
+        self.decibels = decibels;
+        self
+    }
+
+    /// Average the spectra of all frames
+    pub fn average(mut self, average: bool) -> Self {
+        self.average = average;
+        self
+    }
+}
+
+/// One-sided spectra of one or more frames, each `bins` values long
+#[derive(Clone, Debug, PartialEq)]
+pub struct Spectrum {
+    /// Spacing of the frequency bins in Hz
+    pub frequency_step: f64,
+    /// Bins per frame, from DC up to the Nyquist frequency
+    pub bins: usize,
+    /// Peak amplitude of each bin, corrected for the window gain
+    pub magnitude: Vec<f64>,
+    /// Phase of each bin in radians
+    pub phase: Vec<f64>,
+    /// Power spectral density of each bin
+    pub psd: Vec<f64>,
+    /// Whether magnitude and PSD are in decibels
+    pub decibels: bool,
+}
+
+impl Spectrum {
+    /// Number of spectra held
+    pub fn num_frames(&self) -> usize {
+        self.magnitude.len().checked_div(self.bins).unwrap_or(0)
+    }
+
+    /// Frequency of every bin in Hz
+    pub fn frequencies(&self) -> Vec<f64> {
+        (0..self.bins).map(|k| k as f64 * self.frequency_step).collect()
+    }
+
+    /// Magnitude of one spectrum, or `None` if the index is out of range
+    pub fn frame_magnitude(&self, index: usize) -> Option<&[f64]> {
+        self.magnitude.get(index * self.bins..(index + 1) * self.bins)
+    }
+
+    /// Power spectral density of one spectrum, or `None` if the index is out of range
+    pub fn frame_psd(&self, index: usize) -> Option<&[f64]> {
+        self.psd.get(index * self.bins..(index + 1) * self.bins)
+    }
+
+    /// Write one CSV row per bin of every spectrum
+    pub fn write_csv<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        let file = File::create(output_file)?;
+        let mut writer = BufWriter::new(file);
+
+        self.write_csv_to(&mut writer)?;
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the spectra as CSV to any writer
+    pub fn write_csv_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let (magnitude, psd) = if self.decibels { ("dBV", "dB V^2/Hz") } else { ("V", "V^2/Hz") };
+        writeln!(writer, "Frame,Frequency (Hz),Magnitude ({}),Phase (rad),PSD ({})", magnitude, psd)?;
+        let frequencies = self.frequencies();
+        for i in 0..self.num_frames() {
+            for (k, f) in frequencies.iter().enumerate() {
+                let j = i * self.bins + k;
+                writeln!(writer, "{},{},{},{},{}", i, f, self.magnitude[j], self.phase[j], self.psd[j])?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write the spectra as a single 4 × spectra × bins `float64` NPY array
+    ///
+    /// The first axis holds frequency, magnitude, phase and PSD in that
+    /// order, so `frequency, magnitude, phase, psd = np.load(path)` unpacks
+    /// it; the frequency row is repeated for every spectrum.
+    pub fn write_npy<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        let file = File::create(output_file)?;
+        let mut writer = BufWriter::new(file);
+
+        self.write_npy_to(&mut writer)?;
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the spectra as a single NPY array to any writer, see
+    /// [`write_npy`](Self::write_npy)
+    pub fn write_npy_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let frequencies = self.frequencies().repeat(self.num_frames());
+        let values = [frequencies.as_slice(), &self.magnitude, &self.phase, &self.psd].concat();
+        write_f64_npy(writer, &[4, self.num_frames(), self.bins], &values)?;
+        Ok(())
+    }
+
+    /// Write an NPZ archive with the spectra
+    ///
+    /// The archive contains `frequency` (bins), `magnitude`, `phase` and
+    /// `psd` (each spectra × bins `float64`).
+    pub fn write_npz<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        let file = File::create(output_file)?;
+        let mut zip = ZipWriter::new(BufWriter::new(file));
+        let shape = [self.num_frames(), self.bins];
+
+        zip.start_entry("frequency.npy")?;
+        write_f64_npy(&mut zip, &[self.bins], &self.frequencies())?;
+        for (name, values) in [("magnitude", &self.magnitude), ("phase", &self.phase), ("psd", &self.psd)] {
+            zip.start_entry(&format!("{}.npy", name))?;
+            write_f64_npy(&mut zip, &shape, values)?;
+        }
+
+        zip.finish()?.flush()?;
+        Ok(())
+    }
+}
+
+/// Spectrum of a single frame sampled every `sample_interval`
+pub fn spectrum(samples: &[f64], sample_interval: f64, options: &SpectrumOptions) -> Result<Spectrum> {
+    let mut analyzer = Analyzer::new(samples.len(), sample_interval, options)?;
+    analyzer.add(samples);
+    Ok(analyzer.finish(options))
+}
+
+/// Spectra of every frame, or their average, reading each frame once
+pub(crate) fn spectrum_frames<S: Waveform + ?Sized>(source: &S, options: &SpectrumOptions) -> Result<Spectrum> {
+    let mut analyzer = Analyzer::new(source.samples_per_frame(), source.sample_interval(), options)?;
+    for i in 0..source.num_frames() {
+        analyzer.add(&source.try_frame(i)?);
+    }
+    Ok(analyzer.finish(options))
+}
+
+/// Windowed FFT of successive frames with optional averaging
+struct Analyzer {
+    fft: std::sync::Arc<dyn rustfft::Fft<f64>>,
+    window: Vec<f64>,
+    fft_length: usize,
+    sample_rate: f64,
+    average: bool,
+    frames: usize,
+    /// One-sided spectra, or their running sum when averaging
+    spectra: Vec<Complex<f64>>,
+    /// Sum of |X|² per bin when averaging
+    power: Vec<f64>,
+}
+
+impl Analyzer {
+    fn new(samples: usize, sample_interval: f64, options: &SpectrumOptions) -> Result<Self> {
+        let fft_length = options.fft_length.unwrap_or(samples);
+        if samples == 0 || fft_length < samples {
+            return Err(WfmError::ParseError(
+                format!("FFT length {} is shorter than the {} samples per frame", fft_length, samples)
+            ));
+        }
+        Ok(Analyzer {
+            fft: FftPlanner::new().plan_fft_forward(fft_length),
+            window: options.window.coefficients(samples),
+            fft_length,
+            sample_rate: 1.0 / sample_interval,
+            average: options.average,
+            frames: 0,
+            spectra: Vec::new(),
+            power: Vec::new(),
+        })
+    }
+
+    fn bins(&self) -> usize {
+        self.fft_length / 2 + 1
+    }
+
+    fn add(&mut self, samples: &[f64]) {
+        let mut buffer: Vec<Complex<f64>> = samples.iter()
+            .zip(&self.window)
+            .map(|(&v, &w)| Complex::new(v * w, 0.0))
+            .collect();
+        buffer.resize(self.fft_length, Complex::new(0.0, 0.0));
+        self.fft.process(&mut buffer);
+        buffer.truncate(self.bins());
+
+        if !self.average {
+            self.spectra.extend(buffer);
+        } else if self.frames == 0 {
+            self.power = buffer.iter().map(|x| x.norm_sqr()).collect();
+            self.spectra = buffer;
+        } else {
+            for ((s, p), x) in self.spectra.iter_mut().zip(&mut self.power).zip(buffer) {
+                *s += x;
+                *p += x.norm_sqr();
+            }
+        }
+        self.frames += 1;
+    }
+
+    fn finish(self, options: &SpectrumOptions) -> Spectrum {
+        let bins = self.bins();
+        let coherent_gain: f64 = self.window.iter().sum();
+        let noise_gain: f64 = self.window.iter().map(|w| w * w).sum();
+
+        // Power of every bin, averaged across frames if requested
+        let power: Vec<f64> = if self.average {
+            self.power.iter().map(|p| p / self.frames as f64).collect()
+        } else {
+            self.spectra.iter().map(|x| x.norm_sqr()).collect()
+        };
+
+        // Bins other than DC and Nyquist also hold the negative frequencies
+        let one_sided = |k: usize| if k == 0 || (self.fft_length.is_multiple_of(2) && k == bins - 1) { 1.0 } else { 2.0 };
+        let mut magnitude = Vec::with_capacity(power.len());
+        let mut psd = Vec::with_capacity(power.len());
+        for (j, p) in power.iter().enumerate() {
+            let factor = one_sided(j % bins);
+            magnitude.push(factor * p.sqrt() / coherent_gain);
+            psd.push(factor * p / (self.sample_rate * noise_gain));
+        }
+        if options.decibels {
+            magnitude.iter_mut().for_each(|m| *m = 20.0 * m.log10());
+            psd.iter_mut().for_each(|p| *p = 10.0 * p.log10());
+        }
+
+        Spectrum {
+            frequency_step: self.sample_rate / self.fft_length as f64,
+            bins,
+            magnitude,
+            phase: self.spectra.iter().map(|x| x.arg()).collect(),
+            psd,
+            decibels: options.decibels,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Analysis, WfmFile};
+    use std::f64::consts::PI;
+
+    /// `amplitude` sine at bin `bin` of a 256-sample frame at 1 MHz
+    fn sine(amplitude: f64, bin: f64, phase: f64) -> Vec<f64> {
+        (0..256).map(|i| amplitude * (2.0 * PI * bin * i as f64 / 256.0 + phase).sin()).collect()
+    }
+
+    #[test]
+    fn test_spectrum() {
+        let samples = sine(2.0, 16.0, 0.0);
+        for window in [Window::Rectangular, Window::Hann, Window::BlackmanHarris, Window::FlatTop] {
+            let s = spectrum(&samples, 1e-6, &SpectrumOptions::new().window(window)).unwrap();
+            assert_eq!(s.bins, 129);
+            assert!((s.frequencies()[16] - 62_500.0).abs() < 1e-9);
+            assert!((s.magnitude[16] - 2.0).abs() < 1e-9, "{:?}", window);
+        }
+
+        // A sine has a phase of -90° relative to the cosine basis
+        let s = spectrum(&samples, 1e-6, &SpectrumOptions::new().window(Window::Rectangular)).unwrap();
+        assert!((s.phase[16] + PI / 2.0).abs() < 1e-9);
+
+        // Parseval: the PSD integrates to the mean square
+        let total: f64 = s.psd.iter().sum::<f64>() * s.frequency_step;
+        assert!((total - 2.0).abs() < 1e-9);
+
+        // Flat-top reads the amplitude correctly between bins, zero-padded
+        let samples = sine(1.0, 20.5, 0.3);
+        let options = SpectrumOptions::new().window(Window::FlatTop).fft_length(1024).decibels(true);
+        let s = spectrum(&samples, 1e-6, &options).unwrap();
+        assert_eq!(s.bins, 513);
+        let peak = s.magnitude.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        assert!(peak.abs() < 0.01, "{}", peak);
+
+        assert!(spectrum(&samples, 1e-6, &SpectrumOptions::new().fft_length(100)).is_err());
+    }
+
+    #[test]
+    fn test_spectrum_frames() {
+        let mut samples = sine(1.0, 8.0, 0.0);
+        samples.extend(sine(3.0, 8.0, 0.0));
+        let wfm = WfmFile::from_samples(256, 1e-6, 0.0, samples).unwrap();
+
+        let options = SpectrumOptions::new();
+        let s = wfm.spectrum(&options).unwrap();
+        assert_eq!(s.num_frames(), 2);
+        assert!((s.frame_magnitude(1).unwrap()[8] - 3.0).abs() < 1e-9);
+
+        // Power averaging: sqrt((1 + 9) / 2)
+        let averaged = wfm.spectrum(&options.average(true)).unwrap();
+        assert_eq!(averaged.num_frames(), 1);
+        assert!((averaged.magnitude[8] - 5.0f64.sqrt()).abs() < 1e-9);
+        assert_eq!(averaged.frame_psd(0).unwrap().len(), 129);
+
+        let mut csv = Vec::new();
+        s.write_csv_to(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap().lines().count(), 1 + 2 * 129);
+
+        let mut npy = Vec::new();
+        s.write_npy_to(&mut npy).unwrap();
+        let header_len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+        let header = String::from_utf8_lossy(&npy[10..10 + header_len]);
+        assert!(header.contains("'shape': (4, 2, 129)"), "{}", header);
+        let value = |i: usize| {
+            let at = 10 + header_len + 8 * i;
+            f64::from_le_bytes(npy[at..at + 8].try_into().unwrap())
+        };
+        assert_eq!(npy.len(), 10 + header_len + 8 * 4 * 2 * 129);
+        assert_eq!(value(129 + 8), s.frequencies()[8]);
+        assert_eq!(value(2 * 129 + 129 + 8), s.magnitude[129 + 8]);
+        assert_eq!(value(3 * 2 * 129 + 8), s.psd[8]);
+    }
+}
@@ -0,0 +1,242 @@
+// Frame Statistics
+// Single-pass summary statistics per frame and over a whole capture
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::{Result, Waveform};
+
+/// Summary statistics of a set of samples
+///
+/// Non-finite samples are skipped. Without any finite samples the values
+/// are NaN and `count` is 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameStats {
+    /// Number of finite samples
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Root mean square
+    pub rms: f64,
+    /// Population standard deviation
+    pub std_dev: f64,
+    /// `max - min`
+    pub peak_to_peak: f64,
+    /// Sample index of the first minimum
+    pub argmin: usize,
+    /// Sample index of the first maximum
+    pub argmax: usize,
+}
+
+impl FrameStats {
+    /// Compute the statistics of a slice of samples
+    pub fn from_samples(samples: &[f64]) -> Self {
+        let mut acc = Accumulator::default();
+        for (i, &v) in samples.iter().enumerate() {
+            acc.push(i, v);
+        }
+        acc.finish()
+    }
+}
+
+/// Statistics of every frame of a capture and of all frames together
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaptureStats {
+    /// One entry per frame, in frame order
+    pub frames: Vec<FrameStats>,
+    /// All samples of the capture
+    ///
+    /// `argmin` and `argmax` count samples across frames, so the frame is
+    /// `argmin / samples_per_frame` and the sample `argmin % samples_per_frame`.
+    pub overall: FrameStats,
+}
+
+impl CaptureStats {
+    /// Write one CSV row per frame followed by an `all` row for the capture
+    pub fn write_csv<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        let file = File::create(output_file)?;
+        let mut writer = BufWriter::new(file);
+
+        self.write_csv_to(&mut writer)?;
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the statistics table as CSV to any writer
+    pub fn write_csv_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writeln!(writer, "Frame,Count,Min,Max,Mean,RMS,StdDev,PeakToPeak,ArgMin,ArgMax")?;
+        let rows = self.frames.iter()
+            .enumerate()
+            .map(|(i, s)| (i.to_string(), s))
+            .chain(std::iter::once(("all".to_string(), &self.overall)));
+        for (frame, s) in rows {
+            writeln!(writer, "{},{},{},{},{},{},{},{},{},{}",
+                     frame, s.count, s.min, s.max, s.mean, s.rms, s.std_dev, s.peak_to_peak,
+                     s.argmin, s.argmax)?;
+        }
+        Ok(())
+    }
+}
+
+/// Running statistics using Welford's update for the variance
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Accumulator {
+    count: usize,
+    min: f64,
+    max: f64,
+    argmin: usize,
+    argmax: usize,
+    mean: f64,
+    m2: f64,
+    sum_sq: f64,
+}
+
+impl Default for Accumulator {
+    fn default() -> Self {
+        Accumulator {
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            argmin: 0,
+            argmax: 0,
+            mean: 0.0,
+            m2: 0.0,
+            sum_sq: 0.0,
+        }
+    }
+}
+
+impl Accumulator {
+    pub(crate) fn push(&mut self, index: usize, v: f64) {
+        if !v.is_finite() {
+            return;
+        }
+        self.count += 1;
+        if v < self.min {
+            self.min = v;
+            self.argmin = index;
+        }
+        if v > self.max {
+            self.max = v;
+            self.argmax = index;
+        }
+        let delta = v - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (v - self.mean);
+        self.sum_sq += v * v;
+    }
+
+    /// Combine with the statistics of samples whose indices start at `offset`
+    fn merge(&mut self, other: &Accumulator, offset: usize) {
+        if other.count == 0 {
+            return;
+        }
+        if other.min < self.min {
+            self.min = other.min;
+            self.argmin = other.argmin + offset;
+        }
+        if other.max > self.max {
+            self.max = other.max;
+            self.argmax = other.argmax + offset;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.mean += delta * other.count as f64 / count as f64;
+        self.m2 += other.m2 + delta * delta * self.count as f64 * other.count as f64 / count as f64;
+        self.sum_sq += other.sum_sq;
+        self.count = count;
+    }
+
+    pub(crate) fn finish(&self) -> FrameStats {
+        if self.count == 0 {
+            return FrameStats {
+                count: 0,
+                min: f64::NAN,
+                max: f64::NAN,
+                mean: f64::NAN,
+                rms: f64::NAN,
+                std_dev: f64::NAN,
+                peak_to_peak: f64::NAN,
+                argmin: 0,
+                argmax: 0,
+            };
+        }
+        let n = self.count as f64;
+        FrameStats {
+            count: self.count,
+            min: self.min,
+            max: self.max,
+            mean: self.mean,
+            rms: (self.sum_sq / n).sqrt(),
+            std_dev: (self.m2 / n).sqrt(),
+            peak_to_peak: self.max - self.min,
+            argmin: self.argmin,
+            argmax: self.argmax,
+        }
+    }
+}
+
+/// Statistics of every frame and of the whole capture, reading each frame once
+pub(crate) fn capture_stats<S: Waveform + ?Sized>(source: &S) -> Result<CaptureStats> {
+    let mut overall = Accumulator::default();
+    let mut frames = Vec::with_capacity(source.num_frames() as usize);
+
+    for i in 0..source.num_frames() {
+        let mut acc = Accumulator::default();
+        for (j, &v) in source.try_frame(i)?.iter().enumerate() {
+            acc.push(j, v);
+        }
+        overall.merge(&acc, i as usize * source.samples_per_frame());
+        frames.push(acc.finish());
+    }
+
+    Ok(CaptureStats { frames, overall: overall.finish() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::write_test_wfm;
+    use crate::{Analysis, WfmFile, WfmReader};
+
+    #[test]
+    fn test_frame_stats() {
+        let s = FrameStats::from_samples(&[1.0, -3.0, f64::NAN, 5.0, 1.0]);
+        assert_eq!(s.count, 4);
+        assert_eq!((s.min, s.max, s.peak_to_peak), (-3.0, 5.0, 8.0));
+        assert_eq!((s.argmin, s.argmax), (1, 3));
+        assert_eq!(s.mean, 1.0);
+        assert_eq!(s.rms, 3.0);
+        assert!((s.std_dev - 8.0f64.sqrt()).abs() < 1e-12);
+
+        assert!(FrameStats::from_samples(&[]).mean.is_nan());
+    }
+
+    #[test]
+    fn test_capture_stats() {
+        let input = write_test_wfm(3, 50, |f, i| ((i * 7 + f * 13) % 41) as i8 - 20);
+        let wfm = WfmFile::open(input.path()).unwrap();
+        let stats = wfm.stats().unwrap();
+
+        let direct = FrameStats::from_samples(&wfm.file_content.scaled_frames);
+        assert_eq!(stats.overall.count, direct.count);
+        assert_eq!((stats.overall.argmin, stats.overall.argmax), (direct.argmin, direct.argmax));
+        assert!((stats.overall.mean - direct.mean).abs() < 1e-12);
+        assert!((stats.overall.std_dev - direct.std_dev).abs() < 1e-12);
+        assert!((stats.overall.rms - direct.rms).abs() < 1e-12);
+        assert_eq!(stats.frames[1], FrameStats::from_samples(wfm.get_frame(1).unwrap()));
+
+        let reader = WfmReader::open(input.path()).unwrap();
+        assert_eq!(reader.stats().unwrap(), stats);
+        assert_eq!(reader.frame_stats(2).unwrap(), stats.frames[2]);
+
+        let mut csv = Vec::new();
+        stats.write_csv_to(&mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        assert_eq!(csv.lines().count(), 5);
+        assert!(csv.lines().last().unwrap().starts_with("all,150,"));
+    }
+}
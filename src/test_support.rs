@@ -0,0 +1,49 @@
+// Test Support
+// Helpers shared by the unit tests of several modules
+
+use std::io::Write;
+use tempfile::NamedTempFile;
+
+/// Write a WFM v3 FastFrame file whose raw codes are given by `sample(frame, index)`
+///
+/// Uses 10 mV per code, 1 ns per sample and a start time of -100 ns. Frame `n`
+/// triggers at `1_700_000_000 + n` seconds plus `n / 8` of a second.
+pub(crate) fn write_test_wfm<F>(frames: u32, samples: u32, sample: F) -> NamedTempFile
+where
+    F: Fn(u32, u32) -> i8,
+{
+    let mut header = vec![0u8; 838];
+    header[2..10].copy_from_slice(b":WFM#003");
+    header[0x072..0x076].copy_from_slice(&1u32.to_le_bytes());
+    header[0x076..0x07a].copy_from_slice(&1u32.to_le_bytes());
+    header[0x04e..0x052].copy_from_slice(&1u32.to_le_bytes());
+    header[0x048..0x04c].copy_from_slice(&(frames - 1).to_le_bytes());
+    header[0x0a8..0x0b0].copy_from_slice(&0.01f64.to_le_bytes());
+    header[0x1e8..0x1f0].copy_from_slice(&1e-9f64.to_le_bytes());
+    header[0x1f0..0x1f8].copy_from_slice(&(-100e-9f64).to_le_bytes());
+    header[0x33a..0x33e].copy_from_slice(&samples.to_le_bytes());
+    header[0x33e..0x342].copy_from_slice(&samples.to_le_bytes());
+
+    let update_spec = |frame: u32| {
+        let mut spec = vec![0u8; 24];
+        spec[4..12].copy_from_slice(&0.5f64.to_le_bytes());
+        spec[12..20].copy_from_slice(&(frame as f64 / 8.0).to_le_bytes());
+        spec[20..24].copy_from_slice(&(1_700_000_000 + frame as i32).to_le_bytes());
+        spec
+    };
+    header[0x310..0x328].copy_from_slice(&update_spec(0));
+
+    let mut bytes = header;
+    for frame in 1..frames {
+        bytes.extend(update_spec(frame));
+    }
+    bytes.extend(vec![0u8; 30 * (frames - 1) as usize]);
+    for frame in 0..frames {
+        bytes.extend((0..samples).map(|i| sample(frame, i) as u8));
+    }
+
+    let mut temp_file = NamedTempFile::new().unwrap();
+    temp_file.write_all(&bytes).unwrap();
+    temp_file.flush().unwrap();
+    temp_file
+}
@@ -0,0 +1,267 @@
+// Timing Measurements
+// Frequency, period, duty cycle and jitter from threshold crossings
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::measure::{field_statistics, top_base, write_fields_csv};
+use crate::{FrameStats, Result, Waveform};
+
+/// Threshold settings used by [`measure_timing`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimingOptions {
+    /// Absolute threshold level, or `None` for the middle between top and base
+    pub threshold: Option<f64>,
+    /// Width of the hysteresis band around the threshold, as a fraction of
+    /// the amplitude
+    pub hysteresis: f64,
+}
+
+impl Default for TimingOptions {
+    fn default() -> Self {
+        TimingOptions {
+            threshold: None,
+            hysteresis: 0.1,
+        }
+    }
+}
+
+impl TimingOptions {
+    /// Threshold halfway between top and base with 10 % hysteresis
+    pub fn new() -> Self {
+        TimingOptions::default()
+    }
+
+    /// Use an absolute threshold level
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = Some(threshold);
+        self
+    }
+
+    /// Set the hysteresis band width as a fraction of the amplitude
+    pub fn hysteresis(mut self, hysteresis: f64) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+}
+
+/// A threshold crossing at an interpolated sample position
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Crossing {
+    /// Fractional sample index of the crossing
+    pub position: f64,
+    /// Whether the signal crosses upwards
+    pub rising: bool,
+}
+
+/// Crossings of `threshold` that leave the band `threshold ± hysteresis / 2`
+///
+/// A crossing only counts once the signal has passed the far edge of the
+/// band, so noise around the threshold does not add edges. Its position is
+/// the last linearly interpolated threshold crossing before that point.
+pub fn threshold_crossings(samples: &[f64], threshold: f64, hysteresis: f64) -> Vec<Crossing> {
+    let upper = threshold + hysteresis / 2.0;
+    let lower = threshold - hysteresis / 2.0;
+
+    let mut crossings = Vec::new();
+    let mut state: Option<bool> = None;
+    let mut last_up = None;
+    let mut last_down = None;
+
+    for (i, &v) in samples.iter().enumerate() {
+        if i > 0 {
+            let a = samples[i - 1];
+            if a < threshold && v >= threshold {
+                last_up = Some((i - 1) as f64 + (threshold - a) / (v - a));
+            } else if a > threshold && v <= threshold {
+                last_down = Some((i - 1) as f64 + (threshold - a) / (v - a));
+            }
+        }
+
+        if v >= upper && state != Some(true) {
+            if let (Some(false), Some(position)) = (state, last_up) {
+                crossings.push(Crossing { position, rising: true });
+            }
+            state = Some(true);
+        } else if v <= lower && state != Some(false) {
+            if let (Some(true), Some(position)) = (state, last_down) {
+                crossings.push(Crossing { position, rising: false });
+            }
+            state = Some(false);
+        }
+    }
+    crossings
+}
+
+/// Timing measurements of one frame
+///
+/// Times are in the source's horizontal units. Values need at least one
+/// complete cycle (two rising crossings); jitter needs two or three.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TimingMeasurements {
+    /// Number of complete cycles between rising crossings
+    pub cycles: usize,
+    /// Mean time between rising crossings
+    pub period: Option<f64>,
+    /// `1 / period`
+    pub frequency: Option<f64>,
+    /// Mean high time per cycle in percent of the period
+    pub duty_cycle: Option<f64>,
+    /// Standard deviation of the individual periods
+    pub period_jitter: Option<f64>,
+    /// RMS difference between adjacent periods
+    pub cycle_to_cycle_jitter: Option<f64>,
+}
+
+impl TimingMeasurements {
+    /// Names of the measurements, in declaration order
+    pub const FIELD_NAMES: [&'static str; 6] = [
+        "cycles", "period", "frequency", "duty_cycle", "period_jitter", "cycle_to_cycle_jitter",
+    ];
+
+    /// All measurements as name/value pairs, in declaration order
+    pub fn fields(&self) -> [(&'static str, Option<f64>); 6] {
+        let values = [
+            Some(self.cycles as f64), self.period, self.frequency, self.duty_cycle,
+            self.period_jitter, self.cycle_to_cycle_jitter,
+        ];
+        std::array::from_fn(|i| (Self::FIELD_NAMES[i], values[i]))
+    }
+}
+
+/// Timing measurements of every frame of a capture
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimingReport {
+    /// One entry per frame, in frame order
+    pub frames: Vec<TimingMeasurements>,
+}
+
+impl TimingReport {
+    /// Statistics of each measurement across the frames that have it
+    pub fn statistics(&self) -> Vec<(&'static str, FrameStats)> {
+        field_statistics(&TimingMeasurements::FIELD_NAMES, self.frames.iter().map(|m| m.fields().to_vec()))
+    }
+
+    /// Write one CSV row of measurements per frame
+    pub fn write_csv<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
+        let file = File::create(output_file)?;
+        let mut writer = BufWriter::new(file);
+
+        self.write_csv_to(&mut writer)?;
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Write the per-frame measurements as CSV to any writer
+    ///
+    /// Missing measurements are left empty.
+    pub fn write_csv_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        write_fields_csv(writer, &TimingMeasurements::FIELD_NAMES, self.frames.iter().map(|m| m.fields().to_vec()))
+    }
+}
+
+/// Measure frequency, period, duty cycle and jitter of one frame sampled every
+/// `sample_interval`
+pub fn measure_timing(samples: &[f64], sample_interval: f64, options: &TimingOptions) -> TimingMeasurements {
+    let (top, base) = top_base(samples);
+    let threshold = options.threshold.unwrap_or((top + base) / 2.0);
+    let hysteresis = options.hysteresis * (top - base).abs();
+    let crossings = threshold_crossings(samples, threshold, hysteresis);
+
+    let rises: Vec<f64> = crossings.iter().filter(|c| c.rising).map(|c| c.position).collect();
+    let periods: Vec<f64> = rises.windows(2).map(|w| (w[1] - w[0]) * sample_interval).collect();
+
+    let mut m = TimingMeasurements { cycles: periods.len(), ..TimingMeasurements::default() };
+    if periods.is_empty() {
+        return m;
+    }
+
+    let stats = FrameStats::from_samples(&periods);
+    m.period = Some(stats.mean);
+    m.frequency = Some(1.0 / stats.mean);
+    if periods.len() > 1 {
+        let n = periods.len() as f64;
+        m.period_jitter = Some(stats.std_dev * (n / (n - 1.0)).sqrt());
+    }
+    if periods.len() > 2 {
+        let diffs = periods.windows(2).map(|w| (w[1] - w[0]).powi(2)).collect::<Vec<_>>();
+        m.cycle_to_cycle_jitter = Some((diffs.iter().sum::<f64>() / diffs.len() as f64).sqrt());
+    }
+
+    // High time of each complete cycle: rise to the following fall
+    let duties: Vec<f64> = rises.windows(2)
+        .filter_map(|w| {
+            crossings.iter()
+                .find(|c| !c.rising && c.position > w[0] && c.position < w[1])
+                .map(|fall| (fall.position - w[0]) / (w[1] - w[0]) * 100.0)
+        })
+        .collect();
+    if !duties.is_empty() {
+        m.duty_cycle = Some(duties.iter().sum::<f64>() / duties.len() as f64);
+    }
+
+    m
+}
+
+/// Timing measurements of every frame, reading each frame once
+pub(crate) fn timing_frames<S: Waveform + ?Sized>(source: &S, options: &TimingOptions) -> Result<TimingReport> {
+    let frames = (0..source.num_frames())
+        .map(|i| Ok(measure_timing(&source.try_frame(i)?, source.sample_interval(), options)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(TimingReport { frames })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Analysis, WfmFile};
+
+    /// Square wave with a 20-sample period, high for 5 samples per cycle
+    /// and a little noise around the threshold on every edge
+    fn square(samples: usize) -> Vec<f64> {
+        (0..samples)
+            .map(|i| match i % 20 {
+                0 => 0.52,
+                1..=4 => 1.0,
+                5 => 0.48,
+                6 => 0.51,
+                _ => 0.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_threshold_crossings() {
+        let crossings = threshold_crossings(&square(40), 0.5, 0.1);
+        let rising: Vec<bool> = crossings.iter().map(|c| c.rising).collect();
+        assert_eq!(rising, [false, true, false]);
+        assert!((crossings[1].position - (19.0 + 0.5 / 0.52)).abs() < 1e-12);
+
+        // Without hysteresis the noise at sample 6 adds edges
+        assert_eq!(threshold_crossings(&square(40), 0.5, 0.0).len(), 7);
+    }
+
+    #[test]
+    fn test_measure_timing() {
+        let m = measure_timing(&square(200), 1e-6, &TimingOptions::new());
+        assert_eq!(m.cycles, 8);
+        assert!((m.period.unwrap() - 20e-6).abs() < 1e-15);
+        assert!((m.frequency.unwrap() - 50e3).abs() < 1e-6);
+        assert!(m.period_jitter.unwrap() < 1e-15);
+        assert!(m.cycle_to_cycle_jitter.unwrap() < 1e-15);
+
+        let duty = m.duty_cycle.unwrap();
+        let high = 7.0 + 0.01 / 0.51 - 0.5 / 0.52;
+        assert!((duty - high / 20.0 * 100.0).abs() < 1e-9, "{}", duty);
+
+        let mut samples = square(200);
+        samples.extend(vec![0.0; 200]);
+        let wfm = WfmFile::from_samples(200, 1e-6, 0.0, samples).unwrap();
+        let report = wfm.measure_timing(&TimingOptions::new()).unwrap();
+        assert_eq!(report.frames[1].cycles, 0);
+        assert_eq!(report.frames[1].period, None);
+        assert_eq!(report.statistics()[1].1.count, 1);
+    }
+}
@@ -0,0 +1,78 @@
+// Waveform Trait
+// Common view over frame-based waveform data
+
+use std::borrow::Cow;
+
+use crate::{FrameTimestamp, Result, WfmError, WfmHeader};
+
+/// Read-only view of a frame-based waveform.
+///
+/// Exports and analysis routines are written against this trait instead of
+/// reaching into the fields of a particular container, so the same code works
+/// for a fully loaded [`WfmFile`](crate::WfmFile), the lazy
+/// [`WfmReader`](crate::WfmReader) and any other source of frames. Analyses
+/// of a whole capture live in the [`Analysis`](crate::Analysis) extension
+/// trait.
+pub trait Waveform {
+    /// Number of frames available
+    fn num_frames(&self) -> u32;
+
+    /// Number of samples in every frame
+    fn samples_per_frame(&self) -> usize;
+
+    /// Time between consecutive samples, in horizontal units
+    fn sample_interval(&self) -> f64;
+
+    /// Time of the first sample in each frame, in horizontal units
+    fn t0(&self) -> f64;
+
+    /// Unit of the sample values
+    fn vertical_unit(&self) -> &str {
+        "V"
+    }
+
+    /// Unit of the time axis
+    fn horizontal_unit(&self) -> &str {
+        "s"
+    }
+
+    /// Scaled sample values of a frame, or `None` if the index is out of range
+    fn frame(&self, index: u32) -> Option<Cow<'_, [f64]>>;
+
+    /// Scaled sample values of a frame, or an error naming the missing frame
+    fn try_frame(&self, index: u32) -> Result<Cow<'_, [f64]>> {
+        self.frame(index).ok_or_else(|| WfmError::ParseError(
+            format!("Frame {} could not be read (source has {} frames)", index, self.num_frames())
+        ))
+    }
+
+    /// Raw ADC codes of a frame, if the source has them
+    fn raw_frame(&self, _index: u32) -> Option<Cow<'_, [i8]>> {
+        None
+    }
+
+    /// Trigger time of a frame, if the source records one
+    fn frame_timestamp(&self, _index: u32) -> Option<FrameTimestamp> {
+        None
+    }
+
+    /// Path and header of the WFM file behind the frames, if there is one
+    fn source(&self) -> Option<(&str, &WfmHeader)> {
+        None
+    }
+
+    /// Total number of samples across all frames
+    fn total_samples(&self) -> usize {
+        self.num_frames() as usize * self.samples_per_frame()
+    }
+
+    /// Time of a sample index within a frame
+    fn time_at(&self, sample: usize) -> f64 {
+        self.t0() + sample as f64 * self.sample_interval()
+    }
+
+    /// Time axis shared by all frames
+    fn time_values(&self) -> Vec<f64> {
+        (0..self.samples_per_frame()).map(|i| self.time_at(i)).collect()
+    }
+}
@@ -2,11 +2,15 @@
 // TK Ales, 2022
 // Version 1.0 - Corrected version
 
+use std::borrow::Cow;
 use std::fs::File;
-use std::io::{self, BufWriter, Read, Write};
+use std::io::{self, Read};
+use std::ops::Range;
 use std::path::Path;
 use thiserror::Error;
 
+use crate::waveform::Waveform;
+
 #[derive(Error, Debug)]
 pub enum WfmError {
     #[error("IO error: {0}")]
@@ -29,12 +33,31 @@ pub enum WfmError {
     
     #[error("Parse error: {0}")]
     ParseError(String),
+    
+    #[error("Frame range {0}..{1} is outside of the {2} frames in the file")]
+    InvalidFrameRange(u32, u32, u32),
+    
+    #[error("Checksum mismatch: file stores {0:#018x}, computed {1:#018x}")]
+    ChecksumMismatch(u64, u64),
+    
+    #[cfg(feature = "arrow")]
+    #[error("Arrow error: {0}")]
+    Arrow(#[from] arrow::error::ArrowError),
+    
+    #[cfg(feature = "arrow")]
+    #[error("Parquet error: {0}")]
+    Parquet(#[from] parquet::errors::ParquetError),
+    
+    #[cfg(feature = "xlsx")]
+    #[error("Excel error: {0}")]
+    Xlsx(#[from] rust_xlsxwriter::XlsxError),
 }
 
 pub type Result<T> = std::result::Result<T, WfmError>;
 
 /// Representation of the WFM file header as decoded.
 #[derive(Default, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WfmHeader {
     pub wfm_version: String,
     pub num_impl_dim: u8,
@@ -57,7 +80,7 @@ pub struct WfmHeader {
 
 impl WfmHeader {
     /// Parse the header from a byte array
-    fn parse_header(header: &[u8]) -> Result<Self> {
+    pub(crate) fn parse_header(header: &[u8]) -> Result<Self> {
         if header.len() != 838 {
             return Err(WfmError::InvalidHeaderSize(header.len()));
         }
@@ -118,6 +141,74 @@ impl WfmHeader {
         Ok(wfm)
     }
     
+    /// Build a header describing data that did not come from a WFM file
+    ///
+    /// Scaling is set to identity (scale 1, offset 0) and the whole record is
+    /// usable. Fails if the record length does not fit the WFM header.
+    pub fn synthetic(num_frames: u32, record_length: usize, sample_interval: f64, t0: f64) -> Result<Self> {
+        let full_record_length = u16::try_from(record_length)
+            .map_err(|_| WfmError::ParseError(
+                format!("Record length {} exceeds the WFM limit of {}", record_length, u16::MAX)
+            ))?;
+        
+        Ok(WfmHeader {
+            wfm_version: ":WFM#003".to_string(),
+            num_impl_dim: 1,
+            num_expl_dim: 1,
+            time_base: sample_interval,
+            is_fastframe: true,
+            num_fastframes: num_frames,
+            curve_byte_offset: 838 + (num_frames.saturating_sub(1) * 54) as u16,
+            voltage_scale: 1.0,
+            voltage_offset: 0.0,
+            acq_time_start: t0,
+            acq_time_scale: sample_interval,
+            precharge_offset: 0,
+            postcharge_offset: full_record_length,
+            usable_record_length: full_record_length,
+            full_record_length,
+            ..WfmHeader::default()
+        })
+    }
+    
+    /// Byte offset of the curve data in the file
+    ///
+    /// Unlike `curve_byte_offset` this does not overflow for large frame counts.
+    pub(crate) fn curve_data_offset(&self) -> u64 {
+        838 + (self.num_fastframes as u64 - 1) * 54
+    }
+    
+    /// Convert a raw ADC code to a scaled value
+    pub fn scaled_value(&self, raw: i8) -> f64 {
+        (raw as f64 * self.voltage_scale) + self.voltage_offset
+    }
+    
+    /// All header fields as name/value pairs, in declaration order
+    ///
+    /// Used by exporters that store the header as metadata.
+    pub fn fields(&self) -> Vec<(&'static str, HeaderValue)> {
+        use HeaderValue::*;
+        vec![
+            ("wfm_version", Text(self.wfm_version.clone())),
+            ("num_impl_dim", Int(self.num_impl_dim as i64)),
+            ("num_expl_dim", Int(self.num_expl_dim as i64)),
+            ("record_type", Int(self.record_type as i64)),
+            ("expl_dim_type", Int(self.expl_dim_type as i64)),
+            ("time_base", Float(self.time_base)),
+            ("is_fastframe", Bool(self.is_fastframe)),
+            ("num_fastframes", Int(self.num_fastframes as i64)),
+            ("curve_byte_offset", Int(self.curve_byte_offset as i64)),
+            ("voltage_scale", Float(self.voltage_scale)),
+            ("voltage_offset", Float(self.voltage_offset)),
+            ("acq_time_start", Float(self.acq_time_start)),
+            ("acq_time_scale", Float(self.acq_time_scale)),
+            ("precharge_offset", Int(self.precharge_offset as i64)),
+            ("postcharge_offset", Int(self.postcharge_offset as i64)),
+            ("usable_record_length", Int(self.usable_record_length as i64)),
+            ("full_record_length", Int(self.full_record_length as i64)),
+        ]
+    }
+    
     fn read_u32(bytes: &[u8]) -> Result<u32> {
         bytes.try_into()
             .map(u32::from_le_bytes)
@@ -131,15 +222,157 @@ impl WfmHeader {
     }
 }
 
+/// Value of a single header field, see [`WfmHeader::fields`]
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeaderValue {
+    Text(String),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+}
+
+impl HeaderValue {
+    /// Numeric value of the field; text fields have none
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            HeaderValue::Text(_) => None,
+            HeaderValue::Int(v) => Some(*v as f64),
+            HeaderValue::Float(v) => Some(*v),
+            HeaderValue::Bool(v) => Some(*v as u8 as f64),
+        }
+    }
+}
+
+impl std::fmt::Display for HeaderValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            HeaderValue::Text(v) => write!(f, "{}", v),
+            HeaderValue::Int(v) => write!(f, "{}", v),
+            HeaderValue::Float(v) => write!(f, "{}", v),
+            HeaderValue::Bool(v) => write!(f, "{}", v),
+        }
+    }
+}
+
+/// Trigger time of a FastFrame frame, decoded from its update specification
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameTimestamp {
+    /// Whole seconds since the Unix epoch (UTC)
+    pub gmt_seconds: i32,
+    /// Fraction of a second added to `gmt_seconds`
+    pub frac_seconds: f64,
+    /// Trigger position within the first sample interval, in samples
+    pub trigger_offset: f64,
+}
+
+impl FrameTimestamp {
+    /// Size of a WFM update specification in bytes
+    pub(crate) const SPEC_SIZE: usize = 24;
+    
+    /// Parse a 24-byte update specification
+    ///
+    /// The layout is real point offset (u32), trigger time offset (f64),
+    /// fractional second (f64) and GMT second (i32).
+    pub(crate) fn parse(spec: &[u8]) -> Result<Self> {
+        if spec.len() < Self::SPEC_SIZE {
+            return Err(WfmError::ParseError("Truncated frame update specification".to_string()));
+        }
+        Ok(FrameTimestamp {
+            trigger_offset: WfmHeader::read_f64(&spec[4..12])?,
+            frac_seconds: WfmHeader::read_f64(&spec[12..20])?,
+            gmt_seconds: WfmHeader::read_u32(&spec[20..24])? as i32,
+        })
+    }
+    
+    /// Trigger time in seconds since the Unix epoch
+    pub fn unix_seconds(&self) -> f64 {
+        self.gmt_seconds as f64 + self.frac_seconds
+    }
+}
+
 /// Container for WFM file content
 #[derive(Default, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WfmContent {
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_samples::raw"))]
     pub raw_frames: Vec<i8>,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_samples::scaled"))]
     pub scaled_frames: Vec<f64>,
+    /// Trigger time of every loaded frame; empty when the source has none
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub frame_timestamps: Vec<FrameTimestamp>,
+    /// Unit of the scaled values when they are not volts, as for a derived
+    /// phase or frequency
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub vertical_unit: Option<String>,
+}
+
+/// How sample data is held in memory after loading
+#[derive(Default, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StoragePrecision {
+    /// Keep both the raw ADC codes and the scaled `f64` values
+    #[default]
+    Scaled,
+    /// Keep only the raw ADC codes; scaled values are computed on demand
+    RawOnly,
+}
+
+/// Options controlling how a WFM file is loaded
+#[derive(Clone, Debug, Default)]
+pub struct LoadOptions {
+    /// Frames to load, or all frames when `None`
+    pub frames: Option<Range<u32>>,
+    /// Which sample representations are kept in memory
+    pub precision: StoragePrecision,
+    /// Verify the checksum stored after the curve buffer
+    pub verify_checksum: bool,
+    /// Keep the complete frames of a truncated file instead of failing
+    pub recover_truncated: bool,
+    /// Drop the pre- and postcharge samples from every frame
+    pub trim_charge: bool,
+}
+
+impl LoadOptions {
+    /// Create options that load the whole file with default settings
+    pub fn new() -> Self {
+        LoadOptions::default()
+    }
+    
+    /// Only load the given range of frames
+    pub fn frames(mut self, range: Range<u32>) -> Self {
+        self.frames = Some(range);
+        self
+    }
+    
+    /// Select which sample representations are kept in memory
+    pub fn precision(mut self, precision: StoragePrecision) -> Self {
+        self.precision = precision;
+        self
+    }
+    
+    /// Enable or disable checksum verification
+    pub fn verify_checksum(mut self, verify: bool) -> Self {
+        self.verify_checksum = verify;
+        self
+    }
+    
+    /// Enable or disable recovery of truncated files
+    pub fn recover_truncated(mut self, recover: bool) -> Self {
+        self.recover_truncated = recover;
+        self
+    }
+    
+    /// Enable or disable trimming of pre- and postcharge samples
+    pub fn trim_charge(mut self, trim: bool) -> Self {
+        self.trim_charge = trim;
+        self
+    }
 }
 
 /// Main WFM file reader
 #[derive(Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WfmFile {
     pub file_path: String,
     pub file_header: WfmHeader,
@@ -152,101 +385,201 @@ impl WfmFile {
         WfmFile::default()
     }
     
-    /// Load a WFM file from the given path
-    pub fn load_file<P: AsRef<Path>>(&mut self, input_file: P) -> Result<()> {
-        self.file_path = input_file.as_ref().to_string_lossy().to_string();
-        
+    /// Open and fully load a WFM file with default options
+    pub fn open<P: AsRef<Path>>(input_file: P) -> Result<Self> {
+        Self::open_with(input_file, LoadOptions::default())
+    }
+    
+    /// Open and load a WFM file with the given options
+    pub fn open_with<P: AsRef<Path>>(input_file: P, options: LoadOptions) -> Result<Self> {
         let mut file_handle = File::open(&input_file)?;
         
         // Read and parse header
         let mut header_buf = [0u8; 838];
         file_handle.read_exact(&mut header_buf)?;
-        self.file_header = WfmHeader::parse_header(&header_buf)?;
-        
-        // Read curve data
-        self.file_content = WfmContent::default();
-        
-        let total_samples = self.file_header.full_record_length as usize * 
-                           self.file_header.num_fastframes as usize;
-        
-        self.file_content.raw_frames.reserve(total_samples);
-        self.file_content.scaled_frames.reserve(total_samples);
+        let mut file_header = WfmHeader::parse_header(&header_buf)?;
         
         // Read all remaining data
         let mut full_buf = Vec::new();
         file_handle.read_to_end(&mut full_buf)?;
         
-        // Process each frame
-        for record_index in 0..self.file_header.num_fastframes {
-            // full_buf starts after the header, so we need to adjust the offset
-            let offset_b = ((self.file_header.curve_byte_offset - 838) as usize) +
-                          (self.file_header.full_record_length as usize * record_index as usize);
-            let offset_e = offset_b + self.file_header.full_record_length as usize;
-            
-            if offset_e > full_buf.len() {
+        let record_length = file_header.full_record_length as usize;
+        // full_buf starts after the header, so we need to adjust the offset
+        let curve_start = (file_header.curve_data_offset() - 838) as usize;
+        let available = full_buf.len().saturating_sub(curve_start) / record_length.max(1);
+        
+        let total_frames = file_header.num_fastframes;
+        let mut range = options.frames.clone().unwrap_or(0..total_frames);
+        if range.start > range.end || range.end > total_frames {
+            return Err(WfmError::InvalidFrameRange(range.start, range.end, total_frames));
+        }
+        
+        if range.end as usize > available {
+            if !options.recover_truncated || range.start as usize >= available {
+                let offset_e = curve_start + record_length * (available + 1);
                 return Err(WfmError::ParseError(
                     format!("Unexpected end of file at frame {}: offset {} > buffer length {}", 
-                            record_index, offset_e, full_buf.len())
+                            available, offset_e, full_buf.len())
                 ));
             }
+            range.end = available as u32;
+        }
+        
+        if options.verify_checksum {
+            let curve_end = curve_start + record_length * total_frames as usize;
+            Self::verify_checksum(&header_buf, &full_buf, curve_end)?;
+        }
+        
+        // Samples kept from every frame
+        let (keep_b, keep_e) = if options.trim_charge {
+            let pre = file_header.precharge_offset as usize;
+            let post = file_header.postcharge_offset as usize;
+            if pre > post || post > record_length {
+                return Err(WfmError::ParseError(
+                    format!("Invalid charge offsets: precharge {} postcharge {} record length {}",
+                            pre, post, record_length)
+                ));
+            }
+            (pre, post)
+        } else {
+            (0, record_length)
+        };
+        
+        let total_samples = (keep_e - keep_b) * range.len();
+        let mut file_content = WfmContent::default();
+        file_content.raw_frames.reserve(total_samples);
+        if options.precision == StoragePrecision::Scaled {
+            file_content.scaled_frames.reserve(total_samples);
+        }
+        
+        // Frame 0 is described by the main header, the others by the
+        // update specifications that follow it
+        for record_index in range.clone() {
+            let spec = match record_index as usize {
+                0 => &header_buf[0x310..0x328],
+                i => full_buf.get((i - 1) * FrameTimestamp::SPEC_SIZE..i * FrameTimestamp::SPEC_SIZE)
+                    .unwrap_or_default(),
+            };
+            file_content.frame_timestamps.push(FrameTimestamp::parse(spec)?);
+        }
+        
+        // Process each frame
+        for record_index in range.clone() {
+            let offset_b = curve_start + record_length * record_index as usize;
             
             // Convert bytes to signed integers and scale
-            for &byte in &full_buf[offset_b..offset_e] {
+            for &byte in &full_buf[offset_b + keep_b..offset_b + keep_e] {
                 let value = byte as i8;
-                self.file_content.raw_frames.push(value);
+                file_content.raw_frames.push(value);
                 
-                let scaled = (value as f64 * self.file_header.voltage_scale) + 
-                            self.file_header.voltage_offset;
-                self.file_content.scaled_frames.push(scaled);
+                if options.precision == StoragePrecision::Scaled {
+                    file_content.scaled_frames.push(file_header.scaled_value(value));
+                }
             }
         }
         
-        Ok(())
+        file_header.num_fastframes = range.len() as u32;
+        if options.trim_charge {
+            file_header.acq_time_start += keep_b as f64 * file_header.acq_time_scale;
+            file_header.full_record_length = (keep_e - keep_b) as u16;
+            file_header.usable_record_length = file_header.full_record_length;
+            file_header.precharge_offset = 0;
+            file_header.postcharge_offset = file_header.full_record_length;
+        }
+        
+        Ok(WfmFile {
+            file_path: input_file.as_ref().to_string_lossy().to_string(),
+            file_header,
+            file_content,
+        })
     }
     
-    /// Write the scaled data to a CSV file
-    pub fn write_csv<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
-        let file = File::create(output_file)?;
-        let mut writer = BufWriter::new(file);
-        
-        // Write header row
-        writeln!(writer, "Sample,Voltage")?;
-        
-        // Write data
-        for (idx, &value) in self.file_content.scaled_frames.iter().enumerate() {
-            let frame_num = idx / self.file_header.full_record_length as usize;
-            let sample_num = idx % self.file_header.full_record_length as usize;
-            writeln!(writer, "{},{},{}", frame_num, sample_num, value)?;
+    /// Build a file from scaled samples laid out frame after frame
+    ///
+    /// The header comes from [`WfmHeader::synthetic`]; no raw codes are kept.
+    pub fn from_samples(record_length: usize, sample_interval: f64, t0: f64, scaled_frames: Vec<f64>) -> Result<Self> {
+        if record_length == 0 || !scaled_frames.len().is_multiple_of(record_length) {
+            return Err(WfmError::ParseError(
+                format!("{} samples do not divide into frames of {}", scaled_frames.len(), record_length)
+            ));
         }
         
-        writer.flush()?;
+        let num_frames = (scaled_frames.len() / record_length) as u32;
+        Ok(WfmFile {
+            file_path: String::new(),
+            file_header: WfmHeader::synthetic(num_frames, record_length, sample_interval, t0)?,
+            file_content: WfmContent { scaled_frames, ..Default::default() },
+        })
+    }
+    
+    /// Processed frames of `source`, keeping its path, header and unit
+    ///
+    /// Raw codes are not carried over since processed values fall between
+    /// them; the frame count follows from the length of `scaled_frames`.
+    /// Each output frame takes the timestamp of the matching entry of
+    /// `timestamp_frames`. Sources without a WFM header get a synthetic one.
+    pub(crate) fn derived<S, I>(source: &S, scaled_frames: Vec<f64>, timestamp_frames: I) -> Result<WfmFile>
+    where
+        S: Waveform + ?Sized,
+        I: IntoIterator<Item = u32>,
+    {
+        let frame_timestamps = timestamp_frames.into_iter()
+            .filter_map(|i| source.frame_timestamp(i))
+            .collect();
+        let vertical_unit = Some(source.vertical_unit())
+            .filter(|&unit| unit != "V")
+            .map(str::to_string);
+        let Some((file_path, header)) = source.source() else {
+            let mut wfm = WfmFile::from_samples(source.samples_per_frame(), source.sample_interval(),
+                                                source.t0(), scaled_frames)?;
+            wfm.file_content.frame_timestamps = frame_timestamps;
+            wfm.file_content.vertical_unit = vertical_unit;
+            return Ok(wfm);
+        };
+        
+        let num_frames = scaled_frames.len() / source.samples_per_frame().max(1);
+        let mut file_header = header.clone();
+        file_header.num_fastframes = num_frames as u32;
+        file_header.curve_byte_offset = 838 + (num_frames.saturating_sub(1) * 54) as u16;
+        
+        Ok(WfmFile {
+            file_path: file_path.to_string(),
+            file_header,
+            file_content: WfmContent { scaled_frames, frame_timestamps, vertical_unit, ..Default::default() },
+        })
+    }
+    
+    /// Load a WFM file from the given path
+    ///
+    /// On failure `self` is left unchanged.
+    pub fn load_file<P: AsRef<Path>>(&mut self, input_file: P) -> Result<()> {
+        *self = Self::open(input_file)?;
         Ok(())
     }
     
-    /// Write frames as separate columns
-    pub fn write_csv_by_frame<P: AsRef<Path>>(&self, output_file: P) -> Result<()> {
-        let file = File::create(output_file)?;
-        let mut writer = BufWriter::new(file);
-        
-        // Write header
-        write!(writer, "Sample")?;
-        for i in 0..self.file_header.num_fastframes {
-            write!(writer, ",Frame{}", i)?;
-        }
-        writeln!(writer)?;
-        
-        // Write data row by row
-        for sample in 0..self.file_header.full_record_length as usize {
-            write!(writer, "{}", sample)?;
-            
-            for frame in 0..self.file_header.num_fastframes as usize {
-                let idx = frame * self.file_header.full_record_length as usize + sample;
-                write!(writer, ",{}", self.file_content.scaled_frames[idx])?;
-            }
-            writeln!(writer)?;
+    /// Compute scaled values from the raw ADC codes
+    ///
+    /// Only needed after loading with [`StoragePrecision::RawOnly`].
+    pub fn scale_raw(&mut self) {
+        let header = &self.file_header;
+        self.file_content.scaled_frames = self.file_content.raw_frames.iter()
+            .map(|&v| header.scaled_value(v))
+            .collect();
+    }
+    
+    /// Compare the stored file checksum against the sum of all bytes before it
+    fn verify_checksum(header: &[u8], body: &[u8], curve_end: usize) -> Result<()> {
+        let stored = body.get(curve_end..curve_end + 8)
+            .ok_or_else(|| WfmError::ParseError("Missing checksum after curve buffer".to_string()))?;
+        let stored = u64::from_le_bytes(stored.try_into().unwrap());
+        
+        let computed = header.iter()
+            .chain(&body[..curve_end])
+            .fold(0u64, |sum, &b| sum.wrapping_add(b as u64));
+        
+        if stored != computed {
+            return Err(WfmError::ChecksumMismatch(stored, computed));
         }
-        
-        writer.flush()?;
         Ok(())
     }
     
@@ -259,24 +592,67 @@ impl WfmFile {
         let start = frame_index as usize * self.file_header.full_record_length as usize;
         let end = start + self.file_header.full_record_length as usize;
         
-        Some(&self.file_content.scaled_frames[start..end])
+        self.file_content.scaled_frames.get(start..end)
     }
     
     /// Get time values for samples
     pub fn get_time_values(&self) -> Vec<f64> {
-        let mut times = Vec::with_capacity(self.file_header.full_record_length as usize);
-        
-        for i in 0..self.file_header.full_record_length {
-            let t = self.file_header.acq_time_start + 
-                   (i as f64 * self.file_header.acq_time_scale);
-            times.push(t);
+        self.time_values()
+    }
+}
+
+impl Waveform for WfmFile {
+    fn num_frames(&self) -> u32 {
+        self.file_header.num_fastframes
+    }
+    
+    fn samples_per_frame(&self) -> usize {
+        self.file_header.full_record_length as usize
+    }
+    
+    fn sample_interval(&self) -> f64 {
+        self.file_header.acq_time_scale
+    }
+    
+    fn t0(&self) -> f64 {
+        self.file_header.acq_time_start
+    }
+    
+    fn vertical_unit(&self) -> &str {
+        self.file_content.vertical_unit.as_deref().unwrap_or("V")
+    }
+    
+    fn frame(&self, index: u32) -> Option<Cow<'_, [f64]>> {
+        if let Some(frame) = self.get_frame(index) {
+            return Some(Cow::Borrowed(frame));
         }
         
-        times
+        // Fall back to scaling raw codes when only those were kept
+        let raw = self.raw_frame(index)?;
+        Some(Cow::Owned(raw.iter().map(|&v| self.file_header.scaled_value(v)).collect()))
+    }
+    
+    fn raw_frame(&self, index: u32) -> Option<Cow<'_, [i8]>> {
+        if index >= self.num_frames() {
+            return None;
+        }
+        let start = index as usize * self.samples_per_frame();
+        self.file_content.raw_frames
+            .get(start..start + self.samples_per_frame())
+            .map(Cow::Borrowed)
+    }
+    
+    fn frame_timestamp(&self, index: u32) -> Option<FrameTimestamp> {
+        self.file_content.frame_timestamps.get(index as usize).copied()
+    }
+    
+    fn source(&self) -> Option<(&str, &WfmHeader)> {
+        Some((&self.file_path, &self.file_header))
     }
 }
 
 #[cfg(test)]
+#[allow(unused_variables, clippy::unnecessary_cast, clippy::useless_vec)]
 mod tests {
     use super::*;
     use std::io::Write;
@@ -401,4 +777,124 @@ mod tests {
                     "Time value {} mismatch: {} != {}", i, actual, expected);
         }
     }
-}
\ No newline at end of file
+    
+    #[test]
+    fn test_waveform_trait() {
+        let mut wfm = WfmFile::new();
+        wfm.file_header.num_fastframes = 2;
+        wfm.file_header.full_record_length = 2;
+        wfm.file_header.acq_time_start = -1.0;
+        wfm.file_header.acq_time_scale = 0.5;
+        wfm.file_content.scaled_frames = vec![1.0, 2.0, 3.0, 4.0];
+        
+        assert_eq!(wfm.num_frames(), 2);
+        assert_eq!(wfm.samples_per_frame(), 2);
+        assert_eq!(wfm.total_samples(), 4);
+        assert_eq!(wfm.time_values(), vec![-1.0, -0.5]);
+        assert_eq!(wfm.frame(1).unwrap().as_ref(), &[3.0, 4.0]);
+        assert!(wfm.frame(2).is_none());
+    }
+    
+    /// Write a test file with `frames` frames of 1000 samples whose value is the frame index
+    fn create_test_file(frames: u32, checksum: bool) -> NamedTempFile {
+        let mut header = create_test_header();
+        header[0x048..0x04c].copy_from_slice(&(frames - 1).to_le_bytes());
+        header[0x336..0x33a].copy_from_slice(&100u32.to_le_bytes());
+        header[0x33a..0x33e].copy_from_slice(&900u32.to_le_bytes());
+        
+        let mut bytes = header;
+        bytes.extend(vec![0u8; 54 * (frames - 1) as usize]);
+        for frame in 0..frames {
+            bytes.extend(vec![frame as u8; 1000]);
+        }
+        if checksum {
+            let sum = bytes.iter().fold(0u64, |a, &b| a + b as u64);
+            bytes.extend(sum.to_le_bytes());
+        }
+        
+        let mut temp_file = NamedTempFile::new().unwrap();
+        temp_file.write_all(&bytes).unwrap();
+        temp_file.flush().unwrap();
+        temp_file
+    }
+    
+    #[test]
+    fn test_open_with_options() {
+        let temp_file = create_test_file(5, false);
+        
+        let wfm = WfmFile::open_with(temp_file.path(), LoadOptions::new().frames(2..4)).unwrap();
+        assert_eq!(wfm.file_header.num_fastframes, 2);
+        assert_eq!(wfm.get_frame(0).unwrap()[0], 0.02);
+        assert_eq!(wfm.get_frame(1).unwrap()[0], 0.03);
+        
+        let wfm = WfmFile::open_with(temp_file.path(), LoadOptions::new().trim_charge(true)).unwrap();
+        assert_eq!(wfm.file_header.full_record_length, 800);
+        assert_eq!(wfm.file_content.raw_frames.len(), 5 * 800);
+        assert!((wfm.file_header.acq_time_start - (-5e-6 + 100e-9)).abs() < 1e-15);
+        
+        let options = LoadOptions::new().precision(StoragePrecision::RawOnly);
+        let mut wfm = WfmFile::open_with(temp_file.path(), options).unwrap();
+        assert!(wfm.file_content.scaled_frames.is_empty());
+        assert!(wfm.get_frame(1).is_none());
+        assert_eq!(wfm.frame(1).unwrap()[0], 0.01);
+        wfm.scale_raw();
+        assert_eq!(wfm.get_frame(1).unwrap()[0], 0.01);
+        
+        let result = WfmFile::open_with(temp_file.path(), LoadOptions::new().frames(3..6));
+        assert!(matches!(result, Err(WfmError::InvalidFrameRange(3, 6, 5))));
+    }
+    
+    #[test]
+    fn test_truncation_recovery() {
+        let temp_file = create_test_file(5, false);
+        let len = temp_file.as_file().metadata().unwrap().len();
+        temp_file.as_file().set_len(len - 500).unwrap();
+        
+        assert!(WfmFile::open(temp_file.path()).is_err());
+        
+        let options = LoadOptions::new().recover_truncated(true);
+        let wfm = WfmFile::open_with(temp_file.path(), options).unwrap();
+        assert_eq!(wfm.file_header.num_fastframes, 4);
+        assert_eq!(wfm.file_content.scaled_frames.len(), 4000);
+    }
+    
+    #[test]
+    fn test_checksum_verification() {
+        let temp_file = create_test_file(3, true);
+        let options = LoadOptions::new().verify_checksum(true);
+        assert!(WfmFile::open_with(temp_file.path(), options.clone()).is_ok());
+        
+        let temp_file = create_test_file(3, false);
+        assert!(WfmFile::open_with(temp_file.path(), options).is_err());
+    }
+    
+    #[test]
+    fn test_frame_timestamps() {
+        let temp_file = crate::test_support::write_test_wfm(4, 10, |_, _| 0);
+        
+        let wfm = WfmFile::open(temp_file.path()).unwrap();
+        assert_eq!(wfm.file_content.frame_timestamps.len(), 4);
+        assert_eq!(wfm.file_content.frame_timestamps[0].gmt_seconds, 1_700_000_000);
+        assert_eq!(wfm.file_content.frame_timestamps[0].trigger_offset, 0.5);
+        assert_eq!(wfm.file_content.frame_timestamps[3].unix_seconds(), 1_700_000_003.375);
+        
+        let wfm = WfmFile::open_with(temp_file.path(), LoadOptions::new().frames(2..4)).unwrap();
+        assert_eq!(wfm.file_content.frame_timestamps[0].gmt_seconds, 1_700_000_002);
+        assert_eq!(wfm.file_content.frame_timestamps[0].frac_seconds, 0.25);
+    }
+    
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let temp_file = create_test_file(3, false);
+        let wfm = WfmFile::open(temp_file.path()).unwrap();
+        
+        let json = serde_json::to_string(&wfm).unwrap();
+        assert!(json.contains("\"scaled_frames\":\""));
+        
+        let restored: WfmFile = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.file_header.num_fastframes, 3);
+        assert_eq!(restored.file_content.raw_frames, wfm.file_content.raw_frames);
+        assert_eq!(restored.file_content.scaled_frames, wfm.file_content.scaled_frames);
+    }
+}